@@ -10,8 +10,6 @@ a = Analysis(
     pathex=['/Users/dattakiran/Documents/Ivivid/New_ideas/CipherShield/ciphershield/cipherengine'],  # Project directory
     binaries=[('/opt/homebrew/bin/pdftotext', '.')],  # Verify this path
     datas=[
-        ('key.pem', '.'),
-        ('cert.pem', '.'),
         *collect_data_files('presidio_analyzer', include_py_files=True),  # Include Presidio configs and .py files
         *collect_data_files('en_core_web_sm', include_py_files=True),     # Include spaCy model data
         *collect_data_files('en_core_web_lg', include_py_files=True),     # Include spaCy model data
@@ -0,0 +1,178 @@
+import hashlib
+import io
+import os
+import struct
+from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+
+# Chunked AES-256-GCM container shared with the Tauri app (src-tauri/src/crypto.rs).
+# Layout: header || chunk_0 || ... || chunk_n, each chunk is ciphertext || tag.
+# Nonce per chunk: nonce_prefix (7) || counter (u32 BE) || last_flag (1).
+# The header is authenticated as AAD on every chunk.
+#
+# Header (version 2, integers big-endian):
+#   magic "CSHD" (4) | version (1) | suite (1) | chunk_size (4) | nonce_prefix (7)
+#   | key_id (8) | plaintext_len (8) | ext_len (1) | original_ext (ext_len)
+
+MAGIC = b"CSHD"
+FORMAT_VERSION = 2
+SUITE_AES_256_GCM_CHUNKED = 1
+DEFAULT_CHUNK_SIZE = 1024 * 1024
+MAX_CHUNK_SIZE = 64 * 1024 * 1024
+MAX_EXT_LEN = 32
+NONCE_PREFIX_LEN = 7
+KEY_ID_LEN = 8
+FIXED_HEADER_LEN = len(MAGIC) + 1 + 1 + 4 + NONCE_PREFIX_LEN + KEY_ID_LEN + 8 + 1
+TAG_LEN = 16
+KEY_ID_CONTEXT = b"ciphershield-key-id"
+
+
+class ContainerError(ValueError):
+    pass
+
+
+class UnsupportedVersionError(ContainerError):
+    def __init__(self, version: int):
+        super().__init__(f"Unsupported container version {version}")
+        self.version = version
+
+
+def key_id(key: bytes) -> bytes:
+    return hashlib.sha256(KEY_ID_CONTEXT + key).digest()[:KEY_ID_LEN]
+
+
+def _validate_chunk_size(chunk_size: int):
+    if chunk_size <= 0 or chunk_size > MAX_CHUNK_SIZE:
+        raise ContainerError("Invalid container header: chunk size out of range")
+
+
+def _chunk_nonce(prefix: bytes, counter: int, last: bool) -> bytes:
+    if counter > 0xFFFFFFFF:
+        raise ContainerError("File too large for container")
+    return prefix + struct.pack(">I", counter) + (b"\x01" if last else b"\x00")
+
+
+def _read_full(src, size: int) -> bytes:
+    buf = b""
+    while len(buf) < size:
+        part = src.read(size - len(buf))
+        if not part:
+            break
+        buf += part
+    return buf
+
+
+def read_header(src) -> tuple:
+    """Parses a container header, returning (metadata dict, raw header bytes)."""
+    head = _read_full(src, len(MAGIC) + 1)
+    if len(head) < len(MAGIC) + 1:
+        raise ContainerError("Invalid container header: truncated header")
+    if head[:4] != MAGIC:
+        raise ContainerError("Not a CipherShield encrypted file")
+    if head[4] != FORMAT_VERSION:
+        raise UnsupportedVersionError(head[4])
+    rest = _read_full(src, FIXED_HEADER_LEN - len(head))
+    if len(rest) < FIXED_HEADER_LEN - len(head):
+        raise ContainerError("Invalid container header: truncated header")
+    header = head + rest
+    suite = header[5]
+    if suite != SUITE_AES_256_GCM_CHUNKED:
+        raise ContainerError(f"Unsupported cipher suite {suite}")
+    (chunk_size,) = struct.unpack(">I", header[6:10])
+    _validate_chunk_size(chunk_size)
+    (plaintext_len,) = struct.unpack(">Q", header[25:33])
+    ext_len = header[33]
+    if ext_len > MAX_EXT_LEN:
+        raise ContainerError("Invalid container header: extension too long")
+    ext = _read_full(src, ext_len)
+    if len(ext) < ext_len:
+        raise ContainerError("Invalid container header: truncated header")
+    header += ext
+    meta = {
+        "version": header[4],
+        "suite": suite,
+        "chunk_size": chunk_size,
+        "nonce_prefix": header[10:17],
+        "key_id": header[17:25],
+        "plaintext_len": plaintext_len,
+        "original_ext": ext.decode("utf-8"),
+    }
+    return meta, header
+
+
+def encrypt_stream(src, dst, key: bytes, plaintext_len: int, chunk_size: int = DEFAULT_CHUNK_SIZE, original_ext: str = "") -> dict:
+    _validate_chunk_size(chunk_size)
+    ext = (original_ext or "").encode("utf-8")
+    if len(ext) > MAX_EXT_LEN:
+        raise ContainerError("Invalid container header: extension too long")
+    prefix = os.urandom(NONCE_PREFIX_LEN)
+    header = (
+        MAGIC
+        + bytes([FORMAT_VERSION, SUITE_AES_256_GCM_CHUNKED])
+        + struct.pack(">I", chunk_size)
+        + prefix
+        + key_id(key)
+        + struct.pack(">Q", plaintext_len)
+        + bytes([len(ext)])
+        + ext
+    )
+    dst.write(header)
+    aesgcm = AESGCM(key)
+    total = 0
+    counter = 0
+    chunk = _read_full(src, chunk_size)
+    while True:
+        next_chunk = _read_full(src, chunk_size) if len(chunk) == chunk_size else b""
+        last = not next_chunk
+        dst.write(aesgcm.encrypt(_chunk_nonce(prefix, counter, last), chunk, header))
+        total += len(chunk)
+        if last:
+            break
+        chunk = next_chunk
+        counter += 1
+    if total != plaintext_len:
+        raise ContainerError(f"Plaintext length mismatch: header says {plaintext_len} bytes, got {total}")
+    meta, _ = read_header(io.BytesIO(header))
+    return meta
+
+
+def decrypt_stream(src, dst, key: bytes) -> dict:
+    meta, header = read_header(src)
+    if meta["key_id"] != key_id(key):
+        raise ContainerError("File was encrypted with a different key")
+    chunk_size = meta["chunk_size"]
+    prefix = meta["nonce_prefix"]
+    aesgcm = AESGCM(key)
+    total = 0
+    counter = 0
+    block = _read_full(src, chunk_size + TAG_LEN)
+    while True:
+        if len(block) < TAG_LEN:
+            raise ContainerError(f"Chunk {counter} failed authentication")
+        next_block = _read_full(src, chunk_size + TAG_LEN) if len(block) == chunk_size + TAG_LEN else b""
+        last = not next_block
+        try:
+            plain = aesgcm.decrypt(_chunk_nonce(prefix, counter, last), block, header)
+        except Exception:
+            raise ContainerError(f"Chunk {counter} failed authentication")
+        dst.write(plain)
+        total += len(plain)
+        if last:
+            break
+        block = next_block
+        counter += 1
+    if total != meta["plaintext_len"]:
+        raise ContainerError(f"Plaintext length mismatch: header says {meta['plaintext_len']} bytes, got {total}")
+    return meta
+
+
+def encrypt_bytes(plaintext: bytes, key: bytes, chunk_size: int = DEFAULT_CHUNK_SIZE, original_ext: str = "") -> bytes:
+    out = io.BytesIO()
+    encrypt_stream(io.BytesIO(plaintext), out, key, len(plaintext), chunk_size, original_ext)
+    return out.getvalue()
+
+
+def decrypt_bytes(data: bytes, key: bytes) -> tuple:
+    """Returns (plaintext, header metadata)."""
+    out = io.BytesIO()
+    meta = decrypt_stream(io.BytesIO(data), out, key)
+    return out.getvalue(), meta
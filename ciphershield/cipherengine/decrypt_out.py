@@ -1,14 +1,11 @@
 import sys
 import os
-from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+import container
 
 def decrypt_data(encrypted_data: bytes, key: bytes) -> bytes:
-    if len(encrypted_data) < 28:  # 12 nonce + min 16 tag
-        raise ValueError("Invalid encrypted data format")
-    nonce = encrypted_data[:12]
-    ct_tag = encrypted_data[12:]
-    aesgcm = AESGCM(key)
-    return aesgcm.decrypt(nonce, ct_tag, None)
+    plaintext, header = container.decrypt_bytes(encrypted_data, key)
+    print(f"Container v{header['version']} suite {header['suite']} key {header['key_id'].hex()} ext .{header['original_ext']}")
+    return plaintext
 
 def decrypt_file(input_path: str, key_hex: str, output_path: str = None):
     key = bytes.fromhex(key_hex)
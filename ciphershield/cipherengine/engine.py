@@ -8,7 +8,7 @@ import io
 import traceback
 from presidio_analyzer import AnalyzerEngine, PatternRecognizer, Pattern
 from presidio_anonymizer import AnonymizerEngine
-from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+import container
 from fastapi import FastAPI, HTTPException
 from pydantic import BaseModel
 import uvicorn
@@ -72,27 +72,19 @@ def process_text(text: str, action: str, mappings: list = None, custom_recognize
         traceback.print_exc(file=sys.stderr)
         raise
 
-def decrypt_data(encrypted_data: bytes, key: bytes) -> bytes:
+def decrypt_data(encrypted_data: bytes, key: bytes) -> tuple:
     try:
         print(f"[DEBUG] Decrypting data (len={len(encrypted_data)})", file=sys.stderr)
-        if len(encrypted_data) < 28:  # 12 nonce + min 16 tag
-            raise ValueError("Invalid encrypted data format")
-        nonce = encrypted_data[:12]
-        ct_tag = encrypted_data[12:]
-        aesgcm = AESGCM(key)
-        return aesgcm.decrypt(nonce, ct_tag, None)
+        return container.decrypt_bytes(encrypted_data, key)
     except Exception as e:
         print(f"[ERROR] decrypt_data failed: {str(e)}", file=sys.stderr)
         traceback.print_exc(file=sys.stderr)
         raise
 
-def encrypt_data(plaintext: bytes, key: bytes) -> bytes:
+def encrypt_data(plaintext: bytes, key: bytes, chunk_size: int = container.DEFAULT_CHUNK_SIZE, original_ext: str = "") -> bytes:
     try:
         print(f"[DEBUG] Encrypting data (len={len(plaintext)})", file=sys.stderr)
-        nonce = os.urandom(12)
-        aesgcm = AESGCM(key)
-        ct_tag = aesgcm.encrypt(nonce, plaintext, None)
-        return nonce + ct_tag
+        return container.encrypt_bytes(plaintext, key, chunk_size, original_ext)
     except Exception as e:
         print(f"[ERROR] encrypt_data failed: {str(e)}", file=sys.stderr)
         traceback.print_exc(file=sys.stderr)
@@ -108,11 +100,11 @@ def process_file(input_path: str, output_path: str, action: str, password: str,
         with open(input_path, "rb") as f:
             encrypted = f.read()
         print(f"[DEBUG] Read {len(encrypted)} bytes from {input_path}", file=sys.stderr)
-        decrypted_bytes = decrypt_data(encrypted, key)
+        decrypted_bytes, header = decrypt_data(encrypted, key)
         print(f"[DEBUG] Decrypted {len(decrypted_bytes)} bytes", file=sys.stderr)
 
         # Determine file type from original_ext if provided, else fall back to input_path extension
-        ext = original_ext.lower() if original_ext else os.path.splitext(input_path)[1].lower()
+        ext = (header["original_ext"] or original_ext or os.path.splitext(input_path)[1]).lower()
         ext = ext.lstrip('.')  # Normalize by removing leading dot
         print(f"[DEBUG] Normalized file extension: {ext}", file=sys.stderr)
         processed_text = ""
@@ -155,7 +147,8 @@ def process_file(input_path: str, output_path: str, action: str, password: str,
 
         # Encrypt and write output
         print(f"[DEBUG] Encrypting output (len={len(processed_text)} chars)", file=sys.stderr)
-        encrypted_output = encrypt_data(processed_text.encode('utf-8'), key)
+        output_ext = "txt" if ext == "pdf" else ext
+        encrypted_output = encrypt_data(processed_text.encode('utf-8'), key, chunk_size, output_ext)
         with open(output_path, "wb") as f:
             f.write(encrypted_output)
         print(f"[DEBUG] Wrote encrypted output to {output_path}", file=sys.stderr)
@@ -179,6 +172,8 @@ async def process_file_endpoint(request: ProcessFileRequest):
     try:
         result = process_file(request.input_path, request.output_path, request.action, request.password, request.mappings, request.chunk_size, request.original_ext, request.custom_recognizers)
         return result
+    except container.ContainerError as e:
+        raise HTTPException(status_code=422, detail=str(e))
     except Exception as e:
         raise HTTPException(status_code=500, detail=str(e))
 
@@ -0,0 +1,36 @@
+# Replacement values chosen while anonymizing. A value the app already has a replacement
+# for (from a template, or from files earlier in the same batch) keeps it, so every output of
+# a batch can be reversed with one set of mappings. A new value gets a numbered placeholder,
+# <ENTITY_TYPE_n>, that no other value uses, since deanonymizing relies on each replacement
+# standing for exactly one original.
+
+
+class Replacements:
+    def __init__(self, mappings: list = None):
+        self.by_original = {}
+        self.by_replacement = {}
+        for item in mappings or []:
+            original, replacement = item.get("original"), item.get("anonymized")
+            if not original or not replacement:
+                continue
+            if original in self.by_original or replacement in self.by_replacement:
+                continue
+            self.by_original[original] = replacement
+            self.by_replacement[replacement] = original
+
+    def replacement(self, original: str, entity_type: str) -> str:
+        if original not in self.by_original:
+            n = 1
+            while f"<{entity_type}_{n}>" in self.by_replacement:
+                n += 1
+            replacement = f"<{entity_type}_{n}>"
+            self.by_original[original] = replacement
+            self.by_replacement[replacement] = original
+        return self.by_original[original]
+
+    def replacer(self, entity_type: str):
+        # For Presidio's "custom" operator, which passes the matched text
+        return lambda original: self.replacement(original, entity_type)
+
+    def original(self, replacement: str) -> str:
+        return self.by_replacement.get(replacement, "")
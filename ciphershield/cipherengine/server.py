@@ -9,15 +9,43 @@ import traceback
 from presidio_analyzer import AnalyzerEngine, PatternRecognizer, Pattern
 from presidio_analyzer.nlp_engine import SpacyNlpEngine
 from presidio_anonymizer import AnonymizerEngine
-from cryptography.hazmat.primitives.ciphers.aead import AESGCM
-from fastapi import FastAPI, HTTPException
+from presidio_anonymizer.entities import OperatorConfig
+import container
+from replacements import Replacements
+from fastapi import FastAPI, HTTPException, Request
+from fastapi.responses import JSONResponse
 from pydantic import BaseModel
 import uvicorn
 import ssl
+import hmac
+import socketserver
+import struct
+import threading
 import spacy
 
+# Port and per-launch secret chosen by the app; popped so nothing this process starts inherits them
+ENGINE_PORT = int(os.environ.pop("CIPHERSHIELD_ENGINE_PORT", "0"))
+ENGINE_SECRET = os.environ.pop("CIPHERSHIELD_ENGINE_SECRET", "")
+# This install's TLS certificate and key, generated by the app on first run
+ENGINE_CERT = os.environ.pop("CIPHERSHIELD_ENGINE_CERT", "")
+ENGINE_KEY = os.environ.pop("CIPHERSHIELD_ENGINE_KEY", "")
+# Unix socket to also serve documents on, when the app asked for the socket transport
+ENGINE_SOCKET = os.environ.pop("CIPHERSHIELD_ENGINE_SOCKET", "")
+if not ENGINE_PORT or not ENGINE_SECRET or not ENGINE_CERT or not ENGINE_KEY:
+    print("[ERROR] CIPHERSHIELD_ENGINE_PORT, _SECRET, _CERT and _KEY must be set", file=sys.stderr)
+    sys.exit(2)
+
 app = FastAPI()
 
+@app.middleware("http")
+async def require_secret(request: Request, call_next):
+    # Every route, health included, only answers the app instance that launched this engine
+    expected = f"Bearer {ENGINE_SECRET}".encode()
+    supplied = request.headers.get("authorization", "").encode()
+    if not hmac.compare_digest(supplied, expected):
+        return JSONResponse(status_code=401, content={"detail": "Unauthorized"})
+    return await call_next(request)
+
 # Determine base path for bundled resources (handles PyInstaller)
 if getattr(sys, 'frozen', False):
     base_path = sys._MEIPASS
@@ -81,7 +109,7 @@ class ProcessFileRequest(BaseModel):
     original_ext: str = None
     custom_recognizers: list = None
 
-def process_text(text: str, action: str, mappings: list = None, custom_recognizers: list = None) -> dict:
+def process_text(text: str, action: str, mappings: list = None, custom_recognizers: list = None, replacements: Replacements = None) -> dict:
     try:
         print(f"[DEBUG] Processing text with action: {action}", file=sys.stderr)
         analyzer_local = AnalyzerEngine(nlp_engine=loaded_nlp_engine)
@@ -92,18 +120,41 @@ def process_text(text: str, action: str, mappings: list = None, custom_recognize
                 analyzer_local.registry.add_recognizer(recognizer)
         if action == "anonymize":
             results = analyzer_local.analyze(text=text, language='en')
-            score_map = { (result.start, result.end): result.score for result in results }
-            anonymized = anonymizer.anonymize(text=text, analyzer_results=results)
-            items = [
-                {
-                    "original": text[item.start:item.end],
+            # Values given in `mappings` keep their replacement; `replacements` carries the
+            # choices across the parts of one document
+            replacements = replacements or Replacements(mappings)
+            operators = {
+                result.entity_type: OperatorConfig("custom", {"lambda": replacements.replacer(result.entity_type)})
+                for result in results
+            }
+            score_map = {}
+            for result in results:
+                key = (result.entity_type, text[result.start:result.end])
+                score_map[key] = max(score_map.get(key, 0.0), result.score)
+            anonymized = anonymizer.anonymize(text=text, analyzer_results=results, operators=operators)
+            items = []
+            for item in anonymized.items:
+                original = replacements.original(item.text)
+                items.append({
+                    "original": original,
                     "anonymized": item.text,
                     "pii_type": item.entity_type,
-                    "confidence": score_map.get((item.start, item.end), 0.0)
+                    "confidence": score_map.get((item.entity_type, original), 0.0)
+                })
+            return {"text": anonymized.text, "items": items}
+        elif action == "analyze":
+            # Detection only: the text comes back unchanged and items carry no replacement
+            results = analyzer_local.analyze(text=text, language='en')
+            items = [
+                {
+                    "original": text[result.start:result.end],
+                    "anonymized": "",
+                    "pii_type": result.entity_type,
+                    "confidence": result.score
                 }
-                for item in anonymized.items
+                for result in results
             ]
-            return {"text": anonymized.text, "items": items}
+            return {"text": text, "items": items}
         elif action == "deanonymize" and mappings:
             result = text
             for item in mappings:
@@ -116,31 +167,50 @@ def process_text(text: str, action: str, mappings: list = None, custom_recognize
         traceback.print_exc(file=sys.stderr)
         raise
 
-def decrypt_data(encrypted_data: bytes, key: bytes) -> bytes:
-    try:
-        print(f"[DEBUG] Decrypting data (len={len(encrypted_data)})", file=sys.stderr)
-        if len(encrypted_data) < 28:
-            raise ValueError("Invalid encrypted data format")
-        nonce = encrypted_data[:12]
-        ct_tag = encrypted_data[12:]
-        aesgcm = AESGCM(key)
-        return aesgcm.decrypt(nonce, ct_tag, None)
-    except Exception as e:
-        print(f"[ERROR] decrypt_data failed: {str(e)}", file=sys.stderr)
-        traceback.print_exc(file=sys.stderr)
-        raise
-
-def encrypt_data(plaintext: bytes, key: bytes) -> bytes:
-    try:
-        print(f"[DEBUG] Encrypting data (len={len(plaintext)})", file=sys.stderr)
-        nonce = os.urandom(12)
-        aesgcm = AESGCM(key)
-        ct_tag = aesgcm.encrypt(nonce, plaintext, None)
-        return nonce + ct_tag
-    except Exception as e:
-        print(f"[ERROR] encrypt_data failed: {str(e)}", file=sys.stderr)
-        traceback.print_exc(file=sys.stderr)
-        raise
+def process_document(data: bytes, ext: str, action: str, mappings: list = None, custom_recognizers: list = None) -> tuple:
+    # Returns the processed document, the PII items found and the extension of the result
+    ext = ext.lower().lstrip('.')
+    print(f"[DEBUG] Normalized file extension: {ext}", file=sys.stderr)
+    # Shared by every part of the document so a value gets one replacement throughout
+    replacements = Replacements(mappings) if action == "anonymize" else None
+    processed_text = ""
+    items = []
+    if ext == "pdf":
+        print(f"[DEBUG] Processing PDF", file=sys.stderr)
+        try:
+            pdf_file = io.BytesIO(data)
+            pdf = pdftotext.PDF(pdf_file)
+            text = "\n".join(pdf)
+            print(f"[DEBUG] Extracted text from PDF: {len(text)} chars", file=sys.stderr)
+            result = process_text(text, action, mappings, custom_recognizers, replacements)
+            processed_text = result["text"]
+            items = result["items"]
+        except Exception as e:
+            print(f"[ERROR] PDF processing failed: {str(e)}", file=sys.stderr)
+            traceback.print_exc(file=sys.stderr)
+            raise
+    elif ext == "csv":
+        print(f"[DEBUG] Processing CSV", file=sys.stderr)
+        decrypted_text = data.decode('utf-8')
+        reader = csv.reader(decrypted_text.splitlines())
+        processed_rows = []
+        for row in reader:
+            processed_row = []
+            for cell in row:
+                result = process_text(cell, action, mappings, custom_recognizers, replacements)
+                processed_row.append(result["text"])
+                items.extend(result["items"])
+            processed_text += ",".join(processed_row) + "\n"
+    elif ext in ["json", "xml", "txt"]:
+        print(f"[DEBUG] Processing {ext}", file=sys.stderr)
+        decrypted_text = data.decode('utf-8')
+        result = process_text(decrypted_text, action, mappings, custom_recognizers, replacements)
+        processed_text = result["text"]
+        items = result["items"]
+    else:
+        raise ValueError(f"Unsupported file format: {ext}")
+    output_ext = "txt" if ext == "pdf" else ext
+    return processed_text.encode('utf-8'), items, output_ext
 
 def process_file(input_path: str, output_path: str, action: str, password: str, mappings: list = None, chunk_size: int = 1024 * 1024, original_ext: str = None, custom_recognizers: list = None) -> dict:
     try:
@@ -148,53 +218,19 @@ def process_file(input_path: str, output_path: str, action: str, password: str,
         key = bytes.fromhex(password)
         print(f"[DEBUG] Key length: {len(key)}", file=sys.stderr)
         with open(input_path, "rb") as f:
-            encrypted = f.read()
-        print(f"[DEBUG] Read {len(encrypted)} bytes from {input_path}", file=sys.stderr)
-        decrypted_bytes = decrypt_data(encrypted, key)
-        print(f"[DEBUG] Decrypted {len(decrypted_bytes)} bytes", file=sys.stderr)
-        ext = original_ext.lower() if original_ext else os.path.splitext(input_path)[1].lower()
-        ext = ext.lstrip('.')
-        print(f"[DEBUG] Normalized file extension: {ext}", file=sys.stderr)
-        processed_text = ""
-        items = []
-        if ext == "pdf":
-            print(f"[DEBUG] Processing PDF", file=sys.stderr)
-            try:
-                pdf_file = io.BytesIO(decrypted_bytes)
-                pdf = pdftotext.PDF(pdf_file)
-                text = "\n".join(pdf)
-                print(f"[DEBUG] Extracted text from PDF: {len(text)} chars", file=sys.stderr)
-                result = process_text(text, action, mappings, custom_recognizers)
-                processed_text = result["text"]
-                items = result["items"]
-            except Exception as e:
-                print(f"[ERROR] PDF processing failed: {str(e)}", file=sys.stderr)
-                traceback.print_exc(file=sys.stderr)
-                raise
-        elif ext == "csv":
-            print(f"[DEBUG] Processing CSV", file=sys.stderr)
-            decrypted_text = decrypted_bytes.decode('utf-8')
-            reader = csv.reader(decrypted_text.splitlines())
-            processed_rows = []
-            for row in reader:
-                processed_row = []
-                for cell in row:
-                    result = process_text(cell, action, mappings, custom_recognizers)
-                    processed_row.append(result["text"])
-                    items.extend(result["items"])
-                processed_text += ",".join(processed_row) + "\n"
-        elif ext in ["json", "xml", "txt"]:
-            print(f"[DEBUG] Processing {ext}", file=sys.stderr)
-            decrypted_text = decrypted_bytes.decode('utf-8')
-            result = process_text(decrypted_text, action, mappings, custom_recognizers)
-            processed_text = result["text"]
-            items = result["items"]
-        else:
-            raise ValueError(f"Unsupported file format: {ext}")
-        print(f"[DEBUG] Encrypting output (len={len(processed_text)} chars)", file=sys.stderr)
-        encrypted_output = encrypt_data(processed_text.encode('utf-8'), key)
+            # Only the plaintext is held in memory, and only up to the same cap as the socket transport
+            header, _ = container.read_header(f)
+            if header["plaintext_len"] > MAX_DOCUMENT_LEN:
+                raise container.ContainerError(f"Document of {header['plaintext_len']} bytes exceeds the {MAX_DOCUMENT_LEN} byte limit")
+            f.seek(0)
+            decrypted = io.BytesIO()
+            header = container.decrypt_stream(f, decrypted, key)
+        print(f"[DEBUG] Decrypted {header['plaintext_len']} bytes from {input_path}", file=sys.stderr)
+        ext = header["original_ext"] or original_ext or os.path.splitext(input_path)[1]
+        processed, items, output_ext = process_document(decrypted.getvalue(), ext, action, mappings, custom_recognizers)
+        print(f"[DEBUG] Encrypting output (len={len(processed)} bytes)", file=sys.stderr)
         with open(output_path, "wb") as f:
-            f.write(encrypted_output)
+            container.encrypt_stream(io.BytesIO(processed), f, key, len(processed), chunk_size, output_ext)
         print(f"[DEBUG] Wrote encrypted output to {output_path}", file=sys.stderr)
         return {"output_path": output_path, "items": items}
     except Exception as e:
@@ -202,6 +238,92 @@ def process_file(input_path: str, output_path: str, action: str, password: str,
         traceback.print_exc(file=sys.stderr)
         raise
 
+# Framed socket transport: each frame is a 4-byte big-endian length and that many bytes.
+# A request is a JSON header frame (secret, action, mappings, original_ext, plaintext_len,
+# custom_recognizers) followed by the document in frames ending with an empty frame; the
+# reply is a JSON header frame ({"items", "content_length"} or {"error"}) followed, on
+# success, by the result framed the same way. Documents never touch the disk.
+MAX_FRAME_LEN = 16 * 1024 * 1024
+REPLY_CHUNK_SIZE = 1024 * 1024
+# Documents are held in memory while processed, by either transport, so their size is capped up front
+MAX_DOCUMENT_LEN = 64 * 1024 * 1024
+
+def read_exact(stream, n: int) -> bytes:
+    data = bytearray()
+    while len(data) < n:
+        chunk = stream.read(n - len(data))
+        if not chunk:
+            raise ConnectionError("Connection closed mid-frame")
+        data.extend(chunk)
+    return bytes(data)
+
+def read_frame(stream) -> bytes:
+    (length,) = struct.unpack(">I", read_exact(stream, 4))
+    if length > MAX_FRAME_LEN:
+        raise ValueError(f"Frame of {length} bytes exceeds the limit")
+    return read_exact(stream, length)
+
+def write_frame(stream, data: bytes):
+    stream.write(struct.pack(">I", len(data)))
+    stream.write(data)
+
+class SocketHandler(socketserver.StreamRequestHandler):
+    def handle(self):
+        try:
+            request = json.loads(read_frame(self.rfile))
+        except Exception as e:
+            print(f"[ERROR] Bad socket request: {str(e)}", file=sys.stderr)
+            return
+        if not hmac.compare_digest(str(request.get("secret", "")).encode(), ENGINE_SECRET.encode()):
+            write_frame(self.wfile, json.dumps({"error": "Unauthorized"}).encode())
+            return
+        declared = request.get("plaintext_len")
+        if not isinstance(declared, int) or declared < 0:
+            write_frame(self.wfile, json.dumps({"error": "Request is missing the document length"}).encode())
+            return
+        if declared > MAX_DOCUMENT_LEN:
+            write_frame(self.wfile, json.dumps({"error": f"Document of {declared} bytes exceeds the {MAX_DOCUMENT_LEN} byte limit"}).encode())
+            return
+        document = bytearray()
+        while True:
+            frame = read_frame(self.rfile)
+            if not frame:
+                break
+            if len(document) + len(frame) > declared:
+                write_frame(self.wfile, json.dumps({"error": f"Document is longer than the declared {declared} bytes"}).encode())
+                return
+            document.extend(frame)
+        if len(document) != declared:
+            write_frame(self.wfile, json.dumps({"error": f"Document is shorter than the declared {declared} bytes"}).encode())
+            return
+        try:
+            processed, items, _ = process_document(bytes(document), request.get("original_ext") or "", request["action"], request.get("mappings"), request.get("custom_recognizers"))
+        except Exception as e:
+            print(f"[ERROR] Socket request failed: {str(e)}", file=sys.stderr)
+            traceback.print_exc(file=sys.stderr)
+            write_frame(self.wfile, json.dumps({"error": str(e)}).encode())
+            return
+        write_frame(self.wfile, json.dumps({"items": items, "content_length": len(processed)}).encode())
+        for offset in range(0, len(processed), REPLY_CHUNK_SIZE):
+            write_frame(self.wfile, processed[offset:offset + REPLY_CHUNK_SIZE])
+        write_frame(self.wfile, b"")
+
+def serve_socket(path: str):
+    # Owner-only from creation; the app also keeps the socket in a private directory
+    old_umask = os.umask(0o177)
+    try:
+        server = socketserver.ThreadingUnixStreamServer(path, SocketHandler)
+    finally:
+        os.umask(old_umask)
+    server.daemon_threads = True
+    threading.Thread(target=server.serve_forever, daemon=True).start()
+    print(f"[DEBUG] Serving socket transport at {path}", file=sys.stderr)
+
+@app.get("/health")
+async def health_endpoint():
+    # Only reachable once the model above has loaded and the server is listening
+    return {"status": "ok"}
+
 @app.post("/process_text")
 async def process_text_endpoint(request: ProcessTextRequest):
     try:
@@ -215,18 +337,22 @@ async def process_file_endpoint(request: ProcessFileRequest):
     try:
         result = process_file(request.input_path, request.output_path, request.action, request.password, request.mappings, request.chunk_size, request.original_ext, request.custom_recognizers)
         return result
+    except container.ContainerError as e:
+        raise HTTPException(status_code=422, detail=str(e))
     except Exception as e:
         raise HTTPException(status_code=500, detail=str(e))
 
 if __name__ == "__main__":
+    if ENGINE_SOCKET:
+        serve_socket(ENGINE_SOCKET)
     print("[DEBUG] Starting Uvicorn server", file=sys.stderr)
-    print(f"[DEBUG] SSL files exist: key={os.path.exists(os.path.join(base_path, 'key.pem'))}, cert={os.path.exists(os.path.join(base_path, 'cert.pem'))}", file=sys.stderr)
+    print(f"[DEBUG] SSL files exist: key={os.path.exists(ENGINE_KEY)}, cert={os.path.exists(ENGINE_CERT)}", file=sys.stderr)
     uvicorn.run(
         app,  # Use the app object directly
-        host="0.0.0.0",
-        port=8000,
-        ssl_keyfile=os.path.join(base_path, "key.pem"),
-        ssl_certfile=os.path.join(base_path, "cert.pem"),
+        host="127.0.0.1",
+        port=ENGINE_PORT,
+        ssl_keyfile=ENGINE_KEY,
+        ssl_certfile=ENGINE_CERT,
         reload=False,
         log_level="debug"
     )
\ No newline at end of file
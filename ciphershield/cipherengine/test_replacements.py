@@ -0,0 +1,37 @@
+import unittest
+from replacements import Replacements
+
+
+class ReplacementsTest(unittest.TestCase):
+    def test_reuses_given_mappings(self):
+        replacements = Replacements([{"original": "alice@example.com", "anonymized": "<EMAIL_ADDRESS_7>"}])
+        self.assertEqual(replacements.replacement("alice@example.com", "EMAIL_ADDRESS"), "<EMAIL_ADDRESS_7>")
+        self.assertEqual(replacements.original("<EMAIL_ADDRESS_7>"), "alice@example.com")
+
+    def test_new_values_get_unused_placeholders(self):
+        replacements = Replacements([{"original": "alice@example.com", "anonymized": "<EMAIL_ADDRESS_1>"}])
+        self.assertEqual(replacements.replacement("bob@example.com", "EMAIL_ADDRESS"), "<EMAIL_ADDRESS_2>")
+        self.assertEqual(replacements.replacement("carol@example.com", "EMAIL_ADDRESS"), "<EMAIL_ADDRESS_3>")
+        self.assertEqual(replacements.replacement("Carol", "PERSON"), "<PERSON_1>")
+
+    def test_a_value_keeps_its_replacement(self):
+        replacements = Replacements()
+        replace = replacements.replacer("PHONE_NUMBER")
+        self.assertEqual(replace("555-0100"), "<PHONE_NUMBER_1>")
+        self.assertEqual(replace("555-0199"), "<PHONE_NUMBER_2>")
+        self.assertEqual(replace("555-0100"), "<PHONE_NUMBER_1>")
+
+    def test_ignores_conflicting_and_empty_mappings(self):
+        replacements = Replacements([
+            {"original": "alice@example.com", "anonymized": "<EMAIL_ADDRESS_1>"},
+            {"original": "alice@example.com", "anonymized": "<EMAIL_ADDRESS_2>"},
+            {"original": "bob@example.com", "anonymized": "<EMAIL_ADDRESS_1>"},
+            {"original": "carol@example.com", "anonymized": ""},
+        ])
+        self.assertEqual(replacements.replacement("alice@example.com", "EMAIL_ADDRESS"), "<EMAIL_ADDRESS_1>")
+        self.assertEqual(replacements.replacement("bob@example.com", "EMAIL_ADDRESS"), "<EMAIL_ADDRESS_2>")
+        self.assertEqual(replacements.replacement("carol@example.com", "EMAIL_ADDRESS"), "<EMAIL_ADDRESS_3>")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,342 @@
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::sync::OnceLock;
+use std::time::Instant;
+use chrono::{DateTime, SecondsFormat, Utc};
+use ring::digest::{Context, SHA256};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use rusqlite::{Connection, Result as SqlResult};
+use tracing::{error, info, warn};
+use crate::audit_export;
+use crate::db::{self, Database};
+use crate::models::{AuditChainReport, AuditEntry, AuditForwarding};
+use crate::tls;
+
+// Audit entries form a hash chain: each entry stores the hash of the previous
+// entry and its own hash over that link plus its contents, so editing, deleting
+// or reordering entries breaks every later link. When a device key is
+// available each hash is also MACed, so the chain can't simply be recomputed
+// by someone who only has the database file.
+
+// Operation, level and status names shown on the Logs screen
+pub const LOGIN: &str = "LOGIN";
+pub const FAILED_LOGIN: &str = "FAILED_LOGIN";
+pub const FILE_PROCESSED: &str = "FILE_PROCESSED";
+pub const TEXT_PROCESSED: &str = "TEXT_PROCESSED";
+pub const PROCESSING_FAILED: &str = "PROCESSING_FAILED";
+pub const PROCESSING_CANCELLED: &str = "PROCESSING_CANCELLED";
+pub const SETTINGS_UPDATED: &str = "SETTINGS_UPDATED";
+pub const EXPORT_GENERATED: &str = "EXPORT_GENERATED";
+
+pub const INFO: &str = "INFO";
+pub const WARNING: &str = "WARNING";
+pub const ERROR: &str = "ERROR";
+
+pub const SUCCESS: &str = "SUCCESS";
+pub const FAILED: &str = "FAILED";
+
+// Placeholder for events with no file or duration, matching the Logs screen
+const NONE: &str = "-";
+
+// `prev_hash` of the first entry in the chain
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const CHAIN_CONTEXT: &[u8] = b"ciphershield-audit-v1";
+const DEVICE_KEY_FILE: &str = "audit.key";
+
+static DEVICE_KEY: OnceLock<hmac::Key> = OnceLock::new();
+// Entries waiting to be forwarded to syslog, sent in order by a thread of their own
+static FORWARDER: OnceLock<Sender<(AuditForwarding, AuditEntry)>> = OnceLock::new();
+
+// Chain link stored alongside each audit entry
+pub struct ChainSeal {
+    pub prev_hash: String,
+    pub entry_hash: String,
+    pub mac: Option<String>,
+}
+
+// An event to append to the audit log
+pub struct AuditEvent {
+    pub level: &'static str,
+    pub operation: &'static str,
+    pub user: String,
+    pub source: String,
+    pub status: &'static str,
+    pub duration_ms: Option<i64>,
+    pub pii_count: i64,
+    pub details: String,
+}
+
+impl AuditEvent {
+    // A successful operation, logged at INFO
+    pub fn success(operation: &'static str, user: &str) -> Self {
+        AuditEvent {
+            level: INFO,
+            operation,
+            user: user.to_string(),
+            source: NONE.to_string(),
+            status: SUCCESS,
+            duration_ms: None,
+            pii_count: 0,
+            details: String::new(),
+        }
+    }
+
+    // A failed operation with the reason it failed
+    pub fn failure(operation: &'static str, level: &'static str, user: &str, details: &str) -> Self {
+        AuditEvent { level, status: FAILED, details: details.to_string(), ..AuditEvent::success(operation, user) }
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.source = source.to_string();
+        self
+    }
+
+    pub fn details(mut self, details: &str) -> Self {
+        self.details = details.to_string();
+        self
+    }
+
+    pub fn pii_count(mut self, count: usize) -> Self {
+        self.pii_count = count as i64;
+        self
+    }
+
+    // Records the time elapsed since `started`
+    pub fn duration(mut self, started: Instant) -> Self {
+        self.duration_ms = Some(started.elapsed().as_millis() as i64);
+        self
+    }
+
+    fn into_entry(self, id: i64, timestamp: String) -> AuditEntry {
+        AuditEntry {
+            id,
+            timestamp,
+            level: self.level.to_string(),
+            operation: self.operation.to_string(),
+            user: self.user,
+            source: self.source,
+            status: self.status.to_string(),
+            duration_ms: self.duration_ms,
+            pii_count: self.pii_count,
+            details: self.details,
+        }
+    }
+}
+
+// Formats a time the way audit timestamps are stored, so they sort and compare as text
+pub fn format_timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+// Normalizes a user-supplied RFC 3339 time to the stored timestamp format
+pub fn parse_timestamp(value: &str) -> Result<String, String> {
+    let time = DateTime::parse_from_rfc3339(value).map_err(|e| format!("Invalid timestamp {}: {}", value, e))?;
+    Ok(format_timestamp(time.with_timezone(&Utc)))
+}
+
+// Loads the per-install audit MAC key from the app data directory, creating it on first run.
+// Must run before the database is opened so entries sealed during migration are MACed too.
+pub fn load_device_key(data_dir: &Path) -> Result<(), String> {
+    let path = data_dir.join(DEVICE_KEY_FILE);
+    let key = match fs::read(&path) {
+        Ok(key) => key,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let mut key = vec![0u8; 32];
+            SystemRandom::new().fill(&mut key).map_err(|e| e.to_string())?;
+            fs::create_dir_all(data_dir).map_err(|e| e.to_string())?;
+            tls::write_private(&path, &key).map_err(|e| e.to_string())?;
+            info!("Created audit device key at {:?}", path);
+            key
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    let _ = DEVICE_KEY.set(hmac::Key::new(hmac::HMAC_SHA256, &key));
+    Ok(())
+}
+
+// Hashes an entry's contents together with the hash of the entry before it
+fn chain_hash(prev_hash: &str, fields: [&str; 9]) -> String {
+    let mut ctx = Context::new(&SHA256);
+    ctx.update(CHAIN_CONTEXT);
+    ctx.update(prev_hash.as_bytes());
+    for field in fields {
+        // Length-prefix each field so values can't shift between fields
+        ctx.update(&(field.len() as u64).to_be_bytes());
+        ctx.update(field.as_bytes());
+    }
+    hex::encode(ctx.finish())
+}
+
+fn entry_mac(entry_hash: &str) -> Option<String> {
+    DEVICE_KEY.get().map(|key| hex::encode(hmac::sign(key, entry_hash.as_bytes())))
+}
+
+fn seal_fields(prev_hash: &str, fields: [&str; 9]) -> ChainSeal {
+    let entry_hash = chain_hash(prev_hash, fields);
+    ChainSeal { prev_hash: prev_hash.to_string(), mac: entry_mac(&entry_hash), entry_hash }
+}
+
+fn entry_fields<'a>(entry: &'a AuditEntry, duration: &'a str, pii_count: &'a str) -> [&'a str; 9] {
+    [
+        &entry.timestamp,
+        &entry.level,
+        &entry.operation,
+        &entry.user,
+        &entry.source,
+        &entry.status,
+        duration,
+        pii_count,
+        &entry.details,
+    ]
+}
+
+fn seal_entry(prev_hash: &str, entry: &AuditEntry) -> ChainSeal {
+    let duration = entry.duration_ms.map(|d| d.to_string()).unwrap_or_default();
+    let pii_count = entry.pii_count.to_string();
+    seal_fields(prev_hash, entry_fields(entry, &duration, &pii_count))
+}
+
+// Appends an event to the audit log. Failures are reported but don't fail the audited operation.
+// Reading the previous hash and inserting don't race because all database work is serialized.
+pub fn record(conn: &Connection, event: AuditEvent) {
+    let timestamp = format_timestamp(Utc::now());
+    let result = db::last_audit_hash(conn).and_then(|prev_hash| {
+        let prev_hash = prev_hash.unwrap_or_else(|| GENESIS_HASH.to_string());
+        let duration = event.duration_ms.map(|d| d.to_string()).unwrap_or_default();
+        let pii_count = event.pii_count.to_string();
+        let fields = [
+            timestamp.as_str(),
+            event.level,
+            event.operation,
+            &event.user,
+            &event.source,
+            event.status,
+            &duration,
+            &pii_count,
+            &event.details,
+        ];
+        db::insert_audit_entry(conn, &timestamp, &event, &seal_fields(&prev_hash, fields))
+    });
+    match result {
+        Ok(id) => forward(conn, event.into_entry(id, timestamp)),
+        Err(e) => error!("Failed to write audit entry for {}: {}", event.operation, e),
+    }
+}
+
+// Queues a stored entry for the syslog receiver when forwarding is enabled. Sending resolves
+// host names and goes over the network, so it happens off the caller's thread rather than
+// while the database is held.
+fn forward(conn: &Connection, entry: AuditEntry) {
+    match db::get_security_settings(conn) {
+        Ok(settings) if settings.audit_forwarding.enabled => {
+            if forwarder().send((settings.audit_forwarding, entry)).is_err() {
+                warn!("Audit forwarder has stopped");
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to read audit forwarding settings: {}", e),
+    }
+}
+
+fn forwarder() -> &'static Sender<(AuditForwarding, AuditEntry)> {
+    FORWARDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<(AuditForwarding, AuditEntry)>();
+        std::thread::spawn(move || {
+            for (forwarding, entry) in receiver {
+                if let Err(e) = audit_export::forward(&forwarding, &entry) {
+                    warn!("Failed to forward audit entry {} to {}: {}", entry.id, forwarding.target, e);
+                }
+            }
+        });
+        sender
+    })
+}
+
+// Seals entries that have no chain link yet, continuing from the newest sealed entry
+pub fn seal_unchained_entries(conn: &Connection) -> SqlResult<()> {
+    let mut prev_hash = db::last_audit_hash(conn)?.unwrap_or_else(|| GENESIS_HASH.to_string());
+    let unsealed = db::get_audit_chain(conn, true)?;
+    for (entry, _) in &unsealed {
+        let seal = seal_entry(&prev_hash, entry);
+        db::set_audit_seal(conn, entry.id, &seal)?;
+        prev_hash = seal.entry_hash;
+    }
+    if !unsealed.is_empty() {
+        info!("Sealed {} audit entries written before chaining", unsealed.len());
+    }
+    Ok(())
+}
+
+// Walks the whole chain and reports the first entry whose link, contents or MAC don't check out
+pub fn verify_chain(conn: &Connection) -> SqlResult<AuditChainReport> {
+    let chain = db::get_audit_chain(conn, false)?;
+    let device_key = DEVICE_KEY.get();
+    let mut report = AuditChainReport {
+        generated_at: format_timestamp(Utc::now()),
+        valid: true,
+        total_entries: chain.len(),
+        verified_entries: 0,
+        mac_verified: device_key.is_some(),
+        first_broken_id: None,
+        reason: None,
+        head_hash: None,
+    };
+    let mut prev_hash = GENESIS_HASH.to_string();
+    for (entry, seal) in &chain {
+        let problem = match seal {
+            None => Some("Entry has no chain link".to_string()),
+            Some(seal) if seal.prev_hash != prev_hash => {
+                Some("Link to the previous entry doesn't match; entries before this one were removed, reordered or edited".to_string())
+            }
+            Some(seal) if seal_entry(&seal.prev_hash, entry).entry_hash != seal.entry_hash => {
+                Some("Entry contents don't match their hash; the entry was edited".to_string())
+            }
+            Some(seal) => match (device_key, &seal.mac) {
+                (Some(key), Some(mac)) => {
+                    let valid = hex::decode(mac).is_ok_and(|mac| hmac::verify(key, seal.entry_hash.as_bytes(), &mac).is_ok());
+                    (!valid).then(|| "Entry MAC doesn't match the device key; the chain was recomputed".to_string())
+                }
+                (Some(_), None) => Some("Entry is missing its MAC".to_string()),
+                (None, _) => None,
+            },
+        };
+        if let Some(reason) = problem {
+            report.valid = false;
+            report.first_broken_id = Some(entry.id);
+            report.reason = Some(reason);
+            break;
+        }
+        prev_hash = seal.as_ref().map(|s| s.entry_hash.clone()).unwrap_or_default();
+        report.verified_entries += 1;
+    }
+    if report.valid {
+        // AUTOINCREMENT remembers the highest id handed out, which reveals deleted trailing entries
+        let last_id = chain.last().map_or(0, |(entry, _)| entry.id);
+        if db::audit_log_sequence(conn)?.is_some_and(|seq| seq > last_id) {
+            report.valid = false;
+            report.first_broken_id = Some(last_id + 1);
+            report.reason = Some(format!("Entries after id {} were deleted", last_id));
+        } else {
+            report.head_hash = chain.last().and_then(|(_, seal)| seal.as_ref().map(|s| s.entry_hash.clone()));
+        }
+    }
+    if !report.valid {
+        warn!("Audit chain verification failed at entry {:?}: {:?}", report.first_broken_id, report.reason);
+    }
+    Ok(report)
+}
+
+// Appends an event from async code without blocking the runtime
+pub async fn record_async(database: &Database, event: AuditEvent) {
+    let result = database
+        .run(move |conn| {
+            record(conn, event);
+            Ok(())
+        })
+        .await;
+    if let Err(e) = result {
+        error!("Failed to write audit entry: {}", e);
+    }
+}
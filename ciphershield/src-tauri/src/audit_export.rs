@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use chrono::DateTime;
+use crate::audit;
+use crate::models::{AuditEntry, AuditExportFormat, AuditForwarding};
+
+// Serializes audit entries for SIEM ingestion: CSV and JSON Lines for file
+// imports, and ArcSight CEF or RFC 5424 syslog lines for collectors. The
+// forwarder sends each new entry to a local syslog receiver as it is written.
+
+const VENDOR: &str = "CipherShield";
+const PRODUCT: &str = "CipherShield";
+const APP_NAME: &str = "ciphershield";
+// Structured-data ID; 32473 is the private enterprise number reserved for documentation
+const SD_ID: &str = "ciphershield@32473";
+// Syslog facility 13 is "log audit"
+const SYSLOG_FACILITY: u8 = 13;
+const UDP_PREFIX: &str = "udp://";
+const CSV_HEADER: &str = "id,timestamp,level,operation,user,source,status,duration_ms,pii_count,details";
+
+// Writes entries to `path` in the chosen format, returning how many were written
+pub fn write_entries(path: &Path, format: AuditExportFormat, entries: &[AuditEntry]) -> Result<usize, String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut out = BufWriter::new(file);
+    if format == AuditExportFormat::Csv {
+        writeln!(out, "{}", CSV_HEADER).map_err(|e| e.to_string())?;
+    }
+    for entry in entries {
+        writeln!(out, "{}", format_entry(entry, format)?).map_err(|e| e.to_string())?;
+    }
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(entries.len())
+}
+
+// Formats a single entry as one line of the given format
+pub fn format_entry(entry: &AuditEntry, format: AuditExportFormat) -> Result<String, String> {
+    Ok(match format {
+        AuditExportFormat::Csv => csv_line(entry),
+        AuditExportFormat::Jsonl => serde_json::to_string(entry).map_err(|e| e.to_string())?,
+        AuditExportFormat::Cef => cef_line(entry),
+        AuditExportFormat::Syslog => syslog_line(entry, &entry.details),
+    })
+}
+
+fn csv_field(value: &str) -> String {
+    // Prefix values a spreadsheet would evaluate as a formula
+    let value = match value.chars().next() {
+        Some('=' | '+' | '@' | '\t' | '\r') => format!("'{}", value),
+        Some('-') if value.len() > 1 => format!("'{}", value),
+        _ => value.to_string(),
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn csv_line(entry: &AuditEntry) -> String {
+    [
+        entry.id.to_string(),
+        csv_field(&entry.timestamp),
+        csv_field(&entry.level),
+        csv_field(&entry.operation),
+        csv_field(&entry.user),
+        csv_field(&entry.source),
+        csv_field(&entry.status),
+        entry.duration_ms.map(|d| d.to_string()).unwrap_or_default(),
+        entry.pii_count.to_string(),
+        csv_field(&entry.details),
+    ]
+    .join(",")
+}
+
+fn cef_header_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_extension_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn cef_severity(level: &str) -> u8 {
+    match level {
+        audit::ERROR => 8,
+        audit::WARNING => 6,
+        _ => 3,
+    }
+}
+
+fn cef_line(entry: &AuditEntry) -> String {
+    let mut extension = vec![
+        format!("externalId={}", entry.id),
+        format!("suser={}", cef_extension_escape(&entry.user)),
+        format!("fname={}", cef_extension_escape(&entry.source)),
+        format!("outcome={}", cef_extension_escape(&entry.status)),
+        format!("cnt={}", entry.pii_count),
+    ];
+    if let Ok(time) = DateTime::parse_from_rfc3339(&entry.timestamp) {
+        extension.insert(0, format!("rt={}", time.timestamp_millis()));
+    }
+    if let Some(duration) = entry.duration_ms {
+        extension.push(format!("cn1={} cn1Label=durationMs", duration));
+    }
+    if !entry.details.is_empty() {
+        extension.push(format!("msg={}", cef_extension_escape(&entry.details)));
+    }
+    format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        cef_header_escape(VENDOR),
+        cef_header_escape(PRODUCT),
+        cef_header_escape(env!("CARGO_PKG_VERSION")),
+        cef_header_escape(&entry.operation),
+        cef_header_escape(&entry.operation.replace('_', " ").to_lowercase()),
+        cef_severity(&entry.level),
+        extension.join(" ")
+    )
+}
+
+fn syslog_severity(level: &str) -> u8 {
+    match level {
+        audit::ERROR => 3,
+        audit::WARNING => 4,
+        _ => 6,
+    }
+}
+
+fn sd_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+// Header fields must be printable ASCII without spaces; "-" is the syslog nil value
+fn header_field(value: &str, max_len: usize) -> String {
+    let field: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max_len).collect();
+    if field.is_empty() { "-".to_string() } else { field }
+}
+
+fn hostname() -> String {
+    let name = std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")).unwrap_or_default();
+    header_field(&name, 255)
+}
+
+// Formats an RFC 5424 syslog message carrying the entry as structured data
+fn syslog_line(entry: &AuditEntry, message: &str) -> String {
+    let mut params = vec![
+        format!("id=\"{}\"", entry.id),
+        format!("user=\"{}\"", sd_escape(&entry.user)),
+        format!("source=\"{}\"", sd_escape(&entry.source)),
+        format!("status=\"{}\"", sd_escape(&entry.status)),
+        format!("piiCount=\"{}\"", entry.pii_count),
+    ];
+    if let Some(duration) = entry.duration_ms {
+        params.push(format!("durationMs=\"{}\"", duration));
+    }
+    let message = message.replace(['\r', '\n'], " ");
+    format!(
+        "<{}>1 {} {} {} {} {} [{} {}]{}{}",
+        SYSLOG_FACILITY * 8 + syslog_severity(&entry.level),
+        header_field(&entry.timestamp, 128),
+        hostname(),
+        APP_NAME,
+        std::process::id(),
+        header_field(&entry.operation, 32),
+        SD_ID,
+        params.join(" "),
+        if message.is_empty() { "" } else { " " },
+        message
+    )
+}
+
+// Checks forwarding settings before they are saved
+pub fn validate_forwarding(forwarding: &AuditForwarding) -> Result<(), String> {
+    if !forwarding.enabled {
+        return Ok(());
+    }
+    if !matches!(forwarding.format, AuditExportFormat::Cef | AuditExportFormat::Syslog) {
+        return Err("Audit forwarding supports the CEF and syslog formats".to_string());
+    }
+    match forwarding.target.strip_prefix(UDP_PREFIX) {
+        Some("") => Err("Audit forwarding needs a udp://host:port address".to_string()),
+        Some(_) => Ok(()),
+        None if cfg!(unix) && !forwarding.target.is_empty() => Ok(()),
+        None => Err("Audit forwarding needs a syslog socket path or a udp://host:port address".to_string()),
+    }
+}
+
+// Sends one entry to the configured syslog receiver as a single datagram
+pub fn forward(forwarding: &AuditForwarding, entry: &AuditEntry) -> Result<(), String> {
+    let message = match forwarding.format {
+        AuditExportFormat::Cef => syslog_line(entry, &cef_line(entry)),
+        _ => syslog_line(entry, &entry.details),
+    };
+    if let Some(address) = forwarding.target.strip_prefix(UDP_PREFIX) {
+        let target = address
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or_else(|| format!("{} did not resolve to an address", address))?;
+        // Bound in the target's address family so IPv6 receivers work too
+        let local: SocketAddr = match target {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local).map_err(|e| e.to_string())?;
+        socket.send_to(message.as_bytes(), target).map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+    #[cfg(unix)]
+    {
+        let socket = std::os::unix::net::UnixDatagram::unbound().map_err(|e| e.to_string())?;
+        socket.send_to(message.as_bytes(), &forwarding.target).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    Err("Syslog sockets are only available on Unix; use a udp://host:port target".to_string())
+}
@@ -1,170 +1,416 @@
-use rusqlite::{params, Connection, Result as SqlResult, OptionalExtension};
-use std::fs::create_dir_all;
-use std::path::PathBuf;
-use tauri::{AppHandle, Manager, command};
+use rusqlite::Connection;
+use tauri::{AppHandle, Manager, State, command};
 use tracing::info;
-use ring::pbkdf2;
-use hex;
-use std::num::NonZeroU32;
-use crate::models::{Template, MappingItem, CustomRecognizer, ProcessedFile};
-
-// Opens and initializes a secure SQLite database in the app's local data directory
-pub fn get_secure_db(app_handle: &AppHandle) -> SqlResult<Connection> {
-    let data_dir = app_handle.path().app_local_data_dir().unwrap_or(PathBuf::from("."));
-    info!("Data directory: {:?}", data_dir);
-    create_dir_all(&data_dir).map_err(|_| rusqlite::Error::ExecuteReturnedResults)?;
-    let db_path = data_dir.join("secure.db");
-    info!("Opening DB at: {:?}", db_path);
-    let conn = Connection::open(&db_path)?;
-    init_schema(&conn)?;
-    info!("DB initialized");
-    Ok(conn)
-}
-
-// Creates the database schema with users, templates, and processed_files tables
-fn init_schema(conn: &Connection) -> SqlResult<()> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS users (
-            email TEXT PRIMARY KEY,
-            password_hash TEXT NOT NULL,
-            salt TEXT NOT NULL
-        );",
-        [],
-    )?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS templates (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            mappings TEXT NOT NULL,
-            custom_recognizers TEXT NOT NULL DEFAULT '[]'
-        );",
-        [],
-    )?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS processed_files (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            path TEXT NOT NULL,
-            timestamp TEXT NOT NULL
-        );",
-        [],
-    )?;
-    Ok(())
+use chrono::Utc;
+use crate::audit::{self, AuditEvent};
+use crate::db::{self, Database};
+use crate::keystore;
+use crate::password;
+use crate::totp;
+use crate::session::SessionState;
+use crate::models::{
+    PasswordPolicy, RegisterInput, ChangePasswordInput, DeleteAccountInput, AccountOutput, LoginOutput, SecuritySettings,
+    VerifyMfaInput, ConfirmMfaInput, DisableMfaInput, MfaEnrollment,
+};
+
+const LOCKOUT_BASE_SECS: i64 = 30;
+const LOCKOUT_MAX_SECS: i64 = 60 * 60;
+const INVALID_CREDENTIALS: &str = "Invalid email or password";
+const INVALID_MFA_CODE: &str = "Invalid verification code";
+
+// Checks a password against the stored record for a user, returning false if the user doesn't exist
+fn check_user_password(conn: &Connection, email: &str, password: &str) -> Result<bool, String> {
+    match db::get_user_hash_salt(conn, email).map_err(|e| e.to_string())? {
+        Some((stored_hash, salt)) => password::verify_password(password, &stored_hash, &salt),
+        None => Ok(false),
+    }
 }
 
-// Inserts a new user into the users table with email, hashed password, and salt
-pub fn insert_user(conn: &Connection, email: &str, hash: &str, salt: &str) -> SqlResult<()> {
-    conn.execute(
-        "INSERT OR IGNORE INTO users (email, password_hash, salt) VALUES (?1, ?2, ?3)",
-        params![email, hash, salt],
-    )?;
-    Ok(())
+// Returns how long to lock an account after `failed_count` consecutive failures, doubling
+// with every attempt past the configured maximum
+fn lockout_seconds(failed_count: u32, max_attempts: u32) -> Option<i64> {
+    let max_attempts = max_attempts.max(1);
+    if failed_count < max_attempts {
+        return None;
+    }
+    let doublings = (failed_count - max_attempts).min(16);
+    Some((LOCKOUT_BASE_SECS << doublings).min(LOCKOUT_MAX_SECS))
+}
+
+fn locked_message(remaining_secs: i64) -> String {
+    format!("Too many failed attempts. Try again in {} seconds.", remaining_secs.max(1))
+}
+
+fn login_failure(message: String) -> LoginOutput {
+    LoginOutput { success: false, error: Some(message), ..Default::default() }
+}
+
+// Counts a failed password or second-factor attempt towards the account lockout
+fn record_login_failure(conn: &Connection, email: &str, failed_count: u32, settings: &SecuritySettings, now: i64, message: &str) -> Result<LoginOutput, String> {
+    let failed_count = failed_count.saturating_add(1);
+    let locked_until = lockout_seconds(failed_count, settings.max_login_attempts).map(|secs| now + secs);
+    db::record_failed_login(conn, email, failed_count, locked_until, now).map_err(|e| e.to_string())?;
+    info!("Failed login attempt {} for {}", failed_count, email);
+    let details = match locked_until {
+        Some(until) => format!("{} (attempt {}, locked for {} seconds)", message, failed_count, until - now),
+        None => format!("{} (attempt {})", message, failed_count),
+    };
+    audit::record(conn, AuditEvent::failure(audit::FAILED_LOGIN, audit::WARNING, email, &details));
+    match locked_until {
+        Some(until) => Ok(login_failure(locked_message(until - now))),
+        None => Ok(login_failure(message.to_string())),
+    }
+}
+
+// Rejects a sign-in attempt while the account is locked
+fn locked_out(conn: &Connection, email: &str, remaining_secs: i64) -> LoginOutput {
+    let message = locked_message(remaining_secs);
+    audit::record(conn, AuditEvent::failure(audit::FAILED_LOGIN, audit::WARNING, email, &message));
+    login_failure(message)
+}
+
+// Finishes a login once every required factor has been checked and the session has started
+fn complete_login(conn: &Connection, email: &str, token: String, details: &str) -> Result<LoginOutput, String> {
+    db::clear_login_attempts(conn, email).map_err(|e| e.to_string())?;
+    audit::record(conn, AuditEvent::success(audit::LOGIN, email).details(details));
+    Ok(LoginOutput { success: true, token: Some(token), ..Default::default() })
+}
+
+// Resolves the user for MFA enrollment: either the signed-in user, or one whose login is
+// waiting on enrollment because the policy requires MFA
+fn enrollment_user(session: &SessionState, challenge: Option<&str>, token: Option<&str>) -> Result<String, String> {
+    match challenge {
+        Some(challenge) => match session.mfa_challenge(challenge)? {
+            (email, true) => Ok(email),
+            (_, false) => Err("Invalid verification request".to_string()),
+        },
+        None => session.require(token.unwrap_or_default()),
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
 }
 
-// Retrieves the password hash and salt for a user by email, if they exist
-pub fn get_user_hash_salt(conn: &Connection, email: &str) -> SqlResult<Option<(String, String)>> {
-    conn.query_row(
-        "SELECT password_hash, salt FROM users WHERE email = ?1",
-        params![email],
-        |row| Ok((row.get(0)?, row.get(1)?)),
-    )
-    .optional()
-}
-
-// Inserts a new template into the templates table with name, mappings, and custom recognizers
-pub fn insert_template(conn: &Connection, name: &str, mappings_json: &str, custom_recognizers_json: &str) -> SqlResult<i32> {
-    conn.execute(
-        "INSERT INTO templates (name, mappings, custom_recognizers) VALUES (?1, ?2, ?3)",
-        params![name, mappings_json, custom_recognizers_json],
-    )?;
-    Ok(conn.last_insert_rowid() as i32)
-}
-
-// Retrieves all templates from the templates table
-pub fn get_templates(conn: &Connection) -> SqlResult<Vec<Template>> {
-    let mut stmt = conn.prepare("SELECT id, name, mappings, custom_recognizers FROM templates")?;
-    let templates = stmt.query_map([], |row| {
-        let id: i32 = row.get(0)?;
-        let name: String = row.get(1)?;
-        let mappings_json: String = row.get(2)?;
-        let custom_recognizers_json: String = row.get(3)?;
-        let mappings: Vec<MappingItem> = serde_json::from_str(&mappings_json).unwrap_or_default();
-        let custom_recognizers: Vec<CustomRecognizer> = serde_json::from_str(&custom_recognizers_json).unwrap_or_default();
-        Ok(Template { id, name, mappings, custom_recognizers })
-    })?
-    .collect::<Result<_, _>>()?;
-    Ok(templates)
-}
-
-// Inserts a processed file record into the processed_files table
-pub fn insert_processed_file(conn: &Connection, name: &str, path: &str, timestamp: &str) -> SqlResult<()> {
-    conn.execute(
-        "INSERT INTO processed_files (name, path, timestamp) VALUES (?1, ?2, ?3)",
-        params![name, path, timestamp],
-    )?;
+// Resolves an entered email to the account it belongs to. The stored form is used from then
+// on, since it also keys the account's data key, templates and history; unknown emails
+// come back normalized.
+fn account_email(conn: &Connection, entered: &str) -> Result<String, String> {
+    let email = normalize_email(entered);
+    let stored = db::find_user_email(conn, entered.trim(), &email).map_err(|e| e.to_string())?;
+    Ok(stored.unwrap_or(email))
+}
+
+fn validate_email(email: &str) -> Result<(), String> {
+    match email.split_once('@') {
+        Some((local, domain)) if !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.') => Ok(()),
+        _ => Err("Invalid email address".to_string()),
+    }
+}
+
+// Validates a password against the levels offered on the Settings screen
+pub fn check_password_policy(policy: PasswordPolicy, password: &str, email: &str) -> Result<(), String> {
+    let len = password.chars().count();
+    let has_lower = password.chars().any(|c| c.is_lowercase());
+    let has_upper = password.chars().any(|c| c.is_uppercase());
+    let has_digit = password.chars().any(|c| c.is_ascii_digit());
+    let has_symbol = password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace());
+    let (min_len, mixed_case, digit_and_symbol) = match policy {
+        PasswordPolicy::Basic => (8, false, false),
+        PasswordPolicy::Standard => (8, true, false),
+        PasswordPolicy::Strong => (12, true, true),
+        PasswordPolicy::Enterprise => (16, true, true),
+    };
+    if len < min_len {
+        return Err(format!("Password must be at least {} characters", min_len));
+    }
+    if mixed_case && !(has_lower && has_upper) {
+        return Err("Password must contain both upper and lower case letters".to_string());
+    }
+    if digit_and_symbol && !(has_digit && has_symbol) {
+        return Err("Password must contain at least one digit and one symbol".to_string());
+    }
+    if policy == PasswordPolicy::Enterprise {
+        let chars: Vec<char> = password.chars().collect();
+        if chars.windows(3).any(|w| w[0] == w[1] && w[1] == w[2]) {
+            return Err("Password must not repeat a character three times in a row".to_string());
+        }
+        let local = email.split('@').next().unwrap_or("");
+        if local.len() >= 3 && password.to_lowercase().contains(local) {
+            return Err("Password must not contain your email name".to_string());
+        }
+    }
     Ok(())
 }
 
-// Retrieves all processed files from the processed_files table
-pub fn get_processed_files(conn: &Connection) -> SqlResult<Vec<ProcessedFile>> {
-    let mut stmt = conn.prepare("SELECT id, name, path, timestamp FROM processed_files")?;
-    let files = stmt.query_map([], |row| {
-        Ok(ProcessedFile {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            path: row.get(2)?,
-            timestamp: row.get(3)?,
+fn account_error(message: &str) -> AccountOutput {
+    AccountOutput { success: false, error: Some(message.to_string()) }
+}
+
+// Creates a new local account after checking the configured password policy
+#[command]
+pub async fn register(database: State<'_, Database>, input: RegisterInput) -> Result<AccountOutput, String> {
+    database
+        .run(move |conn| {
+            let email = normalize_email(&input.email);
+            if let Err(e) = validate_email(&email) {
+                return Ok(account_error(&e));
+            }
+            let settings = db::get_security_settings(conn).map_err(|e| e.to_string())?;
+            if let Err(e) = check_password_policy(settings.password_policy, &input.password, &email) {
+                return Ok(account_error(&e));
+            }
+            if db::find_user_email(conn, &email, &email).map_err(|e| e.to_string())?.is_some() {
+                return Ok(account_error("An account with this email already exists"));
+            }
+            let hash = password::hash_password(&input.password)?;
+            db::insert_user(conn, &email, &hash, "").map_err(|e| e.to_string())?;
+            info!("Registered user {}", email);
+            Ok(AccountOutput { success: true, error: None })
         })
-    })?
-    .collect::<Result<_, _>>()?;
-    Ok(files)
+        .await
 }
 
-// Renames a processed file record in the processed_files table
-pub fn rename_processed_file(conn: &Connection, id: i32, new_name: &str) -> SqlResult<()> {
-    conn.execute(
-        "UPDATE processed_files SET name = ?1 WHERE id = ?2",
-        params![new_name, id],
-    )?;
-    Ok(())
+// Changes a user's password, hashing it with the current algorithm and a fresh salt
+#[command]
+pub async fn change_password(app: AppHandle, database: State<'_, Database>, token: String, input: ChangePasswordInput) -> Result<AccountOutput, String> {
+    database
+        .run(move |conn| {
+            let session = app.state::<SessionState>();
+            let user = session.require(&token)?;
+            let email = account_email(conn, &input.email)?;
+            if email != user {
+                return Ok(account_error("You can only change your own password"));
+            }
+            if !check_user_password(conn, &email, &input.current_password)? {
+                return Ok(account_error(INVALID_CREDENTIALS));
+            }
+            let settings = db::get_security_settings(conn).map_err(|e| e.to_string())?;
+            if let Err(e) = check_password_policy(settings.password_policy, &input.new_password, &email) {
+                return Ok(account_error(&e));
+            }
+            // Re-wrap the data key under the new password so encrypted templates stay readable
+            let data_key = keystore::unlock_data_key(conn, &email, &input.current_password)?;
+            let (kdf_salt, wrapped_key) = keystore::wrap_data_key(&data_key, &input.new_password, &email)?;
+            let hash = password::hash_password(&input.new_password)?;
+            let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+            db::update_user_password(&tx, &email, &hash, "").map_err(|e| e.to_string())?;
+            db::save_user_key(&tx, &email, &kdf_salt, &wrapped_key).map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+            info!("Password changed for {}", email);
+            Ok(AccountOutput { success: true, error: None })
+        })
+        .await
 }
 
-// Authenticates a user by verifying their password against the stored hash
+// Deletes a user's account after re-confirming their password
 #[command]
-pub async fn login(app: AppHandle, input: crate::models::LoginInput) -> Result<crate::models::LoginOutput, String> {
-    let db = get_secure_db(&app).map_err(|e| e.to_string())?;
-    let salt: Option<String> = db
-        .query_row(
-            "SELECT salt FROM users WHERE email = ?1",
-            params![input.email],
-            |row| row.get(0),
-        )
-        .optional()
-        .map_err(|e| e.to_string())?;
-    match salt {
-        Some(salt) => {
-            let salt_bytes = hex::decode(salt).map_err(|e| e.to_string())?;
-            let stored_hash: String = db
-                .query_row(
-                    "SELECT password_hash FROM users WHERE email = ?1",
-                    params![input.email],
-                    |row| row.get(0),
-                )
-                .map_err(|e| e.to_string())?;
-            let mut hash = [0u8; 32];
-            pbkdf2::derive(
-                pbkdf2::PBKDF2_HMAC_SHA256,
-                NonZeroU32::new(100000).unwrap(),
-                &salt_bytes,
-                input.password.as_bytes(),
-                &mut hash,
-            );
-            if hex::encode(hash) == stored_hash {
-                Ok(crate::models::LoginOutput { success: true, error: None })
+pub async fn delete_account(app: AppHandle, database: State<'_, Database>, token: String, input: DeleteAccountInput) -> Result<AccountOutput, String> {
+    database
+        .run(move |conn| {
+            let session = app.state::<SessionState>();
+            let user = session.require(&token)?;
+            let email = account_email(conn, &input.email)?;
+            if email != user {
+                return Ok(account_error("You can only delete your own account"));
+            }
+            if !check_user_password(conn, &email, &input.password)? {
+                return Ok(account_error(INVALID_CREDENTIALS));
+            }
+            // All or nothing, so a failure part way through can't leave half an account behind
+            let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+            db::delete_user(&tx, &email).map_err(|e| e.to_string())?;
+            db::delete_mfa(&tx, &email).map_err(|e| e.to_string())?;
+            db::delete_user_templates(&tx, &email).map_err(|e| e.to_string())?;
+            db::delete_user_processed_files(&tx, &email).map_err(|e| e.to_string())?;
+            db::delete_user_key(&tx, &email).map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+            session.end_for_user(&email);
+            info!("Deleted account {}", email);
+            Ok(AccountOutput { success: true, error: None })
+        })
+        .await
+}
+
+// Authenticates a user by verifying their password against the stored hash. Users with MFA
+// enabled, or who must enroll because of the policy, get a challenge for the second step.
+#[command]
+pub async fn login(app: AppHandle, database: State<'_, Database>, input: crate::models::LoginInput) -> Result<LoginOutput, String> {
+    database
+        .run(move |conn| {
+            let session = app.state::<SessionState>();
+            let email = account_email(conn, &input.email)?;
+            let settings = db::get_security_settings(conn).map_err(|e| e.to_string())?;
+            let now = Utc::now().timestamp();
+            let (failed_count, locked_until) = db::get_login_attempts(conn, &email).map_err(|e| e.to_string())?;
+            if let Some(until) = locked_until.filter(|until| *until > now) {
+                return Ok(locked_out(conn, &email, until - now));
+            }
+            // Unknown users still pay for a hash derivation so timing doesn't reveal which emails exist
+            let (valid, rehash) = match db::get_user_hash_salt(conn, &email).map_err(|e| e.to_string())? {
+                Some((stored_hash, salt)) => (password::verify_password(&input.password, &stored_hash, &salt)?, password::needs_rehash(&stored_hash)),
+                None => {
+                    password::dummy_verify(&input.password);
+                    (false, false)
+                }
+            };
+            if !valid {
+                return record_login_failure(conn, &email, failed_count, &settings, now, INVALID_CREDENTIALS);
+            }
+            if rehash {
+                let hash = password::hash_password(&input.password)?;
+                db::update_user_password(conn, &email, &hash, "").map_err(|e| e.to_string())?;
+                info!("Upgraded password hash for {}", email);
+            }
+            let data_key = keystore::unlock_data_key(conn, &email, &input.password)?;
+            let claimed = db::claim_legacy_templates(conn, &email, &data_key).map_err(|e| e.to_string())?;
+            if claimed > 0 {
+                info!("Encrypted {} legacy templates for {}", claimed, email);
+            }
+            // Failed attempts are only cleared once the second factor passes, so a known password
+            // can't be used to reset the lockout while guessing codes
+            let mfa_enabled = db::get_mfa(conn, &email).map_err(|e| e.to_string())?.is_some_and(|(_, enabled, _)| enabled);
+            if mfa_enabled || settings.require_mfa {
+                let challenge = session.begin_mfa_challenge(&email, !mfa_enabled, data_key)?;
+                return Ok(LoginOutput {
+                    mfa_required: mfa_enabled,
+                    mfa_enrollment_required: !mfa_enabled,
+                    mfa_challenge: Some(challenge),
+                    ..Default::default()
+                });
+            }
+            let token = session.start(&email, settings.session_timeout_minutes, data_key)?;
+            complete_login(conn, &email, token, "Signed in with password")
+        })
+        .await
+}
+
+// Completes a login with a TOTP code or an unused recovery code
+#[command]
+pub async fn verify_mfa(app: AppHandle, database: State<'_, Database>, input: VerifyMfaInput) -> Result<LoginOutput, String> {
+    database
+        .run(move |conn| {
+            let session = app.state::<SessionState>();
+            let (email, enrollment) = session.mfa_challenge(&input.challenge)?;
+            if enrollment {
+                return Err("Multi-factor authentication must be set up before signing in".to_string());
+            }
+            let settings = db::get_security_settings(conn).map_err(|e| e.to_string())?;
+            let now = Utc::now().timestamp();
+            let (failed_count, locked_until) = db::get_login_attempts(conn, &email).map_err(|e| e.to_string())?;
+            if let Some(until) = locked_until.filter(|until| *until > now) {
+                session.clear_mfa_challenge();
+                return Ok(locked_out(conn, &email, until - now));
+            }
+            let Some((secret, true, last_used_step)) = db::get_mfa(conn, &email).map_err(|e| e.to_string())? else {
+                return Err("Multi-factor authentication is not enabled".to_string());
+            };
+            let valid = if totp::is_totp_code(&input.code) {
+                let secret = hex::decode(&secret).map_err(|e| e.to_string())?;
+                match totp::verify_code(&secret, &input.code, now, last_used_step) {
+                    Some(step) => {
+                        db::set_mfa_last_used_step(conn, &email, step).map_err(|e| e.to_string())?;
+                        true
+                    }
+                    None => false,
+                }
             } else {
-                Ok(crate::models::LoginOutput { success: false, error: Some("Invalid credentials".to_string()) })
+                let used = db::use_recovery_code(conn, &email, &totp::hash_recovery_code(&input.code), now).map_err(|e| e.to_string())?;
+                if used {
+                    info!("Recovery code used for {}", email);
+                }
+                used
+            };
+            if !valid {
+                return record_login_failure(conn, &email, failed_count, &settings, now, INVALID_MFA_CODE);
             }
-        }
-        None => Ok(crate::models::LoginOutput { success: false, error: Some("User not found".to_string()) }),
+            let token = session.start_from_mfa_challenge(&input.challenge, settings.session_timeout_minutes)?;
+            complete_login(conn, &email, token, "Signed in with password and second factor")
+        })
+        .await
+}
+
+// Generates a new TOTP secret and recovery codes. The secret stays inactive until confirmed
+// with a code from the authenticator app.
+#[command]
+pub async fn start_mfa_enrollment(app: AppHandle, database: State<'_, Database>, challenge: Option<String>, token: Option<String>) -> Result<MfaEnrollment, String> {
+    database
+        .run(move |conn| {
+            let session = app.state::<SessionState>();
+            let email = enrollment_user(&session, challenge.as_deref(), token.as_deref())?;
+            if db::get_mfa(conn, &email).map_err(|e| e.to_string())?.is_some_and(|(_, enabled, _)| enabled) {
+                return Err("Multi-factor authentication is already enabled".to_string());
+            }
+            let secret = totp::generate_secret()?;
+            let recovery_codes = totp::generate_recovery_codes()?;
+            let hashes: Vec<String> = recovery_codes.iter().map(|code| totp::hash_recovery_code(code)).collect();
+            db::save_mfa_enrollment(conn, &email, &hex::encode(&secret), &hashes).map_err(|e| e.to_string())?;
+            info!("MFA enrollment started for {}", email);
+            Ok(MfaEnrollment {
+                secret: totp::base32_encode(&secret),
+                otpauth_uri: totp::otpauth_uri(&secret, &email),
+                recovery_codes,
+            })
+        })
+        .await
+}
+
+// Enables MFA after checking a code from the newly enrolled authenticator. When enrolling
+// during login, this also signs the user in.
+#[command]
+pub async fn confirm_mfa_enrollment(app: AppHandle, database: State<'_, Database>, token: Option<String>, input: ConfirmMfaInput) -> Result<LoginOutput, String> {
+    database
+        .run(move |conn| {
+            let session = app.state::<SessionState>();
+            let email = enrollment_user(&session, input.challenge.as_deref(), token.as_deref())?;
+            let Some((secret, false, _)) = db::get_mfa(conn, &email).map_err(|e| e.to_string())? else {
+                return Err("No multi-factor enrollment in progress".to_string());
+            };
+            let secret = hex::decode(&secret).map_err(|e| e.to_string())?;
+            let Some(step) = totp::verify_code(&secret, &input.code, Utc::now().timestamp(), None) else {
+                return Ok(login_failure(INVALID_MFA_CODE.to_string()));
+            };
+            db::enable_mfa(conn, &email, step).map_err(|e| e.to_string())?;
+            info!("MFA enabled for {}", email);
+            if let Some(challenge) = &input.challenge {
+                let settings = db::get_security_settings(conn).map_err(|e| e.to_string())?;
+                let token = session.start_from_mfa_challenge(challenge, settings.session_timeout_minutes)?;
+                return complete_login(conn, &email, token, "Signed in after enrolling a second factor");
+            }
+            Ok(LoginOutput { success: true, ..Default::default() })
+        })
+        .await
+}
+
+// Turns off MFA for the signed-in user after re-confirming their password
+#[command]
+pub async fn disable_mfa(app: AppHandle, database: State<'_, Database>, token: String, input: DisableMfaInput) -> Result<AccountOutput, String> {
+    database
+        .run(move |conn| {
+            let session = app.state::<SessionState>();
+            let email = session.require(&token)?;
+            let settings = db::get_security_settings(conn).map_err(|e| e.to_string())?;
+            if settings.require_mfa {
+                return Ok(account_error("Multi-factor authentication is required by policy"));
+            }
+            if !check_user_password(conn, &email, &input.password)? {
+                return Ok(account_error(INVALID_CREDENTIALS));
+            }
+            db::delete_mfa(conn, &email).map_err(|e| e.to_string())?;
+            info!("MFA disabled for {}", email);
+            Ok(AccountOutput { success: true, error: None })
+        })
+        .await
+}
+
+// Ends the session identified by the token returned from login
+#[command]
+pub async fn logout(session: State<'_, SessionState>, token: String) -> Result<(), String> {
+    if !session.is_current(&token) {
+        return Err("Invalid session token".to_string());
     }
+    if let Some(email) = session.end() {
+        info!("Session ended for {}", email);
+    }
+    Ok(())
 }
\ No newline at end of file
@@ -1,10 +1,163 @@
-use tauri::{command, AppHandle};
-use crate::db;
-use crate::models::Template;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle, State};
+use tauri_plugin_opener::OpenerExt;
+use crate::audit::{self, AuditEvent};
+use crate::audit_export;
+use crate::db::{self, Database};
+use crate::session::SessionState;
+use crate::wipe;
+use crate::models::{Template, SecuritySettings, AuditQuery, AuditPage, AuditChainReport, AuditExportInput, ProcessedFile, RenameHistoryInput, DeleteHistoryInput};
 
-// Retrieves all templates from the database
+// Retrieves the signed-in user's templates from the database
 #[command]
-pub async fn get_templates(app: AppHandle) -> Result<Vec<Template>, String> {
-    let conn = db::get_secure_db(&app).map_err(|e| e.to_string())?;
-    db::get_templates(&conn).map_err(|e| e.to_string())
-}
\ No newline at end of file
+pub async fn get_templates(database: State<'_, Database>, session: State<'_, SessionState>, token: String) -> Result<Vec<Template>, String> {
+    let (email, data_key) = session.require_data_key(&token)?;
+    database.run(move |conn| db::get_templates(conn, &email, &data_key).map_err(|e| e.to_string())).await
+}
+
+// Retrieves the security settings used for password policy and session handling
+#[command]
+pub async fn get_security_settings(database: State<'_, Database>) -> Result<SecuritySettings, String> {
+    database.run(|conn| db::get_security_settings(conn).map_err(|e| e.to_string())).await
+}
+
+// Persists the security settings edited on the Settings screen; administrators only
+#[command]
+pub async fn update_security_settings(database: State<'_, Database>, session: State<'_, SessionState>, token: String, settings: SecuritySettings) -> Result<SecuritySettings, String> {
+    let user = session.require(&token)?;
+    audit_export::validate_forwarding(&settings.audit_forwarding)?;
+    database
+        .run(move |conn| {
+            if !db::is_admin(conn, &user).map_err(|e| e.to_string())? {
+                let message = "Only an administrator can change security settings";
+                audit::record(conn, AuditEvent::failure(audit::SETTINGS_UPDATED, audit::WARNING, &user, message));
+                return Err(message.to_string());
+            }
+            db::save_security_settings(conn, &settings).map_err(|e| e.to_string())?;
+            let details = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+            audit::record(conn, AuditEvent::success(audit::SETTINGS_UPDATED, &user).details(&details));
+            Ok(settings)
+        })
+        .await
+}
+
+// Retrieves a page of audit log entries matching the Logs screen filters. Only an
+// administrator sees every user's entries; anyone else sees just their own.
+#[command]
+pub async fn query_audit_log(database: State<'_, Database>, session: State<'_, SessionState>, token: String, mut query: AuditQuery) -> Result<AuditPage, String> {
+    let user = session.require(&token)?;
+    database
+        .run(move |conn| {
+            if !db::is_admin(conn, &user).map_err(|e| e.to_string())? {
+                query.user = Some(user);
+            }
+            let (entries, total) = db::query_audit_log(conn, &query)?;
+            Ok(AuditPage { entries, total, page: query.page })
+        })
+        .await
+}
+
+// Writes the audit entries matching the query's filters to a file as CSV, JSON Lines, CEF or
+// syslog; administrators only, since the export covers every user
+#[command]
+pub async fn export_audit_log(database: State<'_, Database>, session: State<'_, SessionState>, token: String, input: AuditExportInput) -> Result<usize, String> {
+    let user = session.require(&token)?;
+    database
+        .run(move |conn| {
+            if !db::is_admin(conn, &user).map_err(|e| e.to_string())? {
+                let message = "Only an administrator can export the audit log";
+                audit::record(conn, AuditEvent::failure(audit::EXPORT_GENERATED, audit::WARNING, &user, message));
+                return Err(message.to_string());
+            }
+            let entries = db::export_audit_log(conn, &input.query)?;
+            let count = audit_export::write_entries(&input.path, input.format, &entries)?;
+            let details = format!("Exported {} entries as {:?}", count, input.format);
+            audit::record(conn, AuditEvent::success(audit::EXPORT_GENERATED, &user).source(&input.path.to_string_lossy()).details(&details));
+            Ok(count)
+        })
+        .await
+}
+
+// Verifies the audit hash chain, optionally writing the report as JSON to `export_path`
+#[command]
+pub async fn verify_audit_chain(
+    database: State<'_, Database>,
+    session: State<'_, SessionState>,
+    token: String,
+    export_path: Option<PathBuf>,
+) -> Result<AuditChainReport, String> {
+    session.require(&token)?;
+    let report = database.run(|conn| audit::verify_chain(conn).map_err(|e| e.to_string())).await?;
+    if let Some(path) = export_path {
+        let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| e.to_string())?;
+    }
+    Ok(report)
+}
+
+// Lists the signed-in user's processed files, newest first
+#[command]
+pub async fn list_history(database: State<'_, Database>, session: State<'_, SessionState>, token: String) -> Result<Vec<ProcessedFile>, String> {
+    let user = session.require(&token)?;
+    database.run(move |conn| db::get_processed_files(conn, &user).map_err(|e| e.to_string())).await
+}
+
+// Renames a history entry; only the display name changes, not the file on disk
+#[command]
+pub async fn rename_history_entry(database: State<'_, Database>, session: State<'_, SessionState>, token: String, input: RenameHistoryInput) -> Result<(), String> {
+    let user = session.require(&token)?;
+    let name = input.name.trim().to_string();
+    if name.is_empty() {
+        return Err("Name cannot be empty".to_string());
+    }
+    database
+        .run(move |conn| {
+            if !db::rename_processed_file(conn, &user, input.id, &name).map_err(|e| e.to_string())? {
+                return Err("History entry not found".to_string());
+            }
+            Ok(())
+        })
+        .await
+}
+
+// Removes a history entry, optionally deleting its output file as well (wiped first with secure deletion on)
+#[command]
+pub async fn delete_history_entry(database: State<'_, Database>, session: State<'_, SessionState>, token: String, input: DeleteHistoryInput) -> Result<(), String> {
+    let user = session.require(&token)?;
+    let (entry, secure_delete) = database
+        .run(move |conn| {
+            let entry = db::get_processed_file(conn, &user, input.id)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| "History entry not found".to_string())?;
+            let secure_delete = db::get_security_settings(conn).map_err(|e| e.to_string())?.secure_delete;
+            db::delete_processed_file(conn, &user, input.id).map_err(|e| e.to_string())?;
+            Ok((entry, secure_delete))
+        })
+        .await?;
+    if input.delete_output {
+        match wipe::remove_file(Path::new(&entry.output_path), secure_delete) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("History entry removed but the output file could not be deleted: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+// Opens a history entry's output file with the system's default application
+#[command]
+pub async fn reopen_output(app: AppHandle, database: State<'_, Database>, session: State<'_, SessionState>, token: String, id: i64) -> Result<(), String> {
+    let user = session.require(&token)?;
+    let entry = database
+        .run(move |conn| {
+            db::get_processed_file(conn, &user, id)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| "History entry not found".to_string())
+        })
+        .await?;
+    if !PathBuf::from(&entry.output_path).is_file() {
+        return Err(format!("Output file {} no longer exists", entry.output_path));
+    }
+    app.opener().open_path(entry.output_path, None::<&str>).map_err(|e| e.to_string())
+}
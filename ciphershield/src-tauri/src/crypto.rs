@@ -0,0 +1,415 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use ring::aead::{Aad, LessSafeKey, Nonce, Tag, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use zeroize::Zeroizing;
+
+// Chunked AES-256-GCM container shared with the cipher-server sidecar.
+//
+// Layout: header || chunk_0 || ... || chunk_n, where every chunk is
+// `ciphertext || tag` and every chunk except the last carries exactly
+// `chunk_size` bytes of plaintext. Each chunk nonce is
+// `nonce_prefix (7) || counter (u32 BE) || last_flag (1)`, and the header
+// bytes are authenticated as AAD on every chunk, so reordering, truncating
+// or extending the stream, or editing any header field, fails authentication.
+//
+// Header (version 2, integers big-endian):
+//   magic "CSHD" (4) | version (1) | suite (1) | chunk_size (4) | nonce_prefix (7)
+//   | key_id (8) | plaintext_len (8) | ext_len (1) | original_ext (ext_len)
+
+pub const MAGIC: [u8; 4] = *b"CSHD";
+pub const FORMAT_VERSION: u8 = 2;
+pub const SUITE_AES_256_GCM_CHUNKED: u8 = 1;
+pub const DEFAULT_CHUNK_SIZE: u32 = 1024 * 1024;
+const MAX_CHUNK_SIZE: u32 = 64 * 1024 * 1024;
+const MAX_EXT_LEN: usize = 32;
+const NONCE_PREFIX_LEN: usize = 7;
+const KEY_ID_LEN: usize = 8;
+const FIXED_HEADER_LEN: usize = MAGIC.len() + 1 + 1 + 4 + NONCE_PREFIX_LEN + KEY_ID_LEN + 8 + 1;
+const TAG_LEN: usize = 16;
+const KEY_ID_CONTEXT: &[u8] = b"ciphershield-key-id";
+
+// Errors raised while reading or writing an encrypted container
+#[derive(Debug)]
+pub enum ContainerError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnsupportedSuite(u8),
+    InvalidHeader(&'static str),
+    KeyMismatch,
+    Authentication { chunk: u32 },
+    LengthMismatch { expected: u64, actual: u64 },
+    TooLarge,
+    Crypto,
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerError::Io(e) => write!(f, "I/O error: {}", e),
+            ContainerError::BadMagic => write!(f, "Not a CipherShield encrypted file"),
+            ContainerError::UnsupportedVersion(v) => write!(f, "Unsupported container version {}", v),
+            ContainerError::UnsupportedSuite(s) => write!(f, "Unsupported cipher suite {}", s),
+            ContainerError::InvalidHeader(reason) => write!(f, "Invalid container header: {}", reason),
+            ContainerError::KeyMismatch => write!(f, "File was encrypted with a different key"),
+            ContainerError::Authentication { chunk } => write!(f, "Chunk {} failed authentication", chunk),
+            ContainerError::LengthMismatch { expected, actual } => {
+                write!(f, "Plaintext length mismatch: header says {} bytes, got {}", expected, actual)
+            }
+            ContainerError::TooLarge => write!(f, "File too large for container"),
+            ContainerError::Crypto => write!(f, "Cryptographic operation failed"),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+impl From<std::io::Error> for ContainerError {
+    fn from(e: std::io::Error) -> Self {
+        ContainerError::Io(e)
+    }
+}
+
+// Metadata describing an encrypted container, authenticated with its contents
+#[derive(Debug, Clone)]
+pub struct ContainerHeader {
+    pub version: u8,
+    pub suite: u8,
+    pub chunk_size: u32,
+    pub key_id: [u8; KEY_ID_LEN],
+    pub plaintext_len: u64,
+    pub original_ext: String,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+}
+
+impl ContainerHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FIXED_HEADER_LEN + self.original_ext.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.suite);
+        bytes.extend_from_slice(&self.chunk_size.to_be_bytes());
+        bytes.extend_from_slice(&self.nonce_prefix);
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.plaintext_len.to_be_bytes());
+        bytes.push(self.original_ext.len() as u8);
+        bytes.extend_from_slice(self.original_ext.as_bytes());
+        bytes
+    }
+
+    // Parses a header, returning it together with its raw bytes for use as AAD
+    fn read_from<R: Read>(reader: &mut R) -> Result<(ContainerHeader, Vec<u8>), ContainerError> {
+        let mut bytes = vec![0u8; FIXED_HEADER_LEN];
+        if read_full(reader, &mut bytes[..MAGIC.len() + 1])? < MAGIC.len() + 1 {
+            return Err(ContainerError::InvalidHeader("truncated header"));
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(ContainerError::BadMagic);
+        }
+        if bytes[4] != FORMAT_VERSION {
+            return Err(ContainerError::UnsupportedVersion(bytes[4]));
+        }
+        if read_full(reader, &mut bytes[MAGIC.len() + 1..])? < FIXED_HEADER_LEN - MAGIC.len() - 1 {
+            return Err(ContainerError::InvalidHeader("truncated header"));
+        }
+        let suite = bytes[5];
+        if suite != SUITE_AES_256_GCM_CHUNKED {
+            return Err(ContainerError::UnsupportedSuite(suite));
+        }
+        let chunk_size = u32::from_be_bytes(*arrayref::array_ref![bytes, 6, 4]);
+        validate_chunk_size(chunk_size)?;
+        let nonce_prefix = *arrayref::array_ref![bytes, 10, NONCE_PREFIX_LEN];
+        let key_id = *arrayref::array_ref![bytes, 17, KEY_ID_LEN];
+        let plaintext_len = u64::from_be_bytes(*arrayref::array_ref![bytes, 25, 8]);
+        let ext_len = bytes[33] as usize;
+        if ext_len > MAX_EXT_LEN {
+            return Err(ContainerError::InvalidHeader("extension too long"));
+        }
+        let mut ext = vec![0u8; ext_len];
+        if read_full(reader, &mut ext)? < ext_len {
+            return Err(ContainerError::InvalidHeader("truncated header"));
+        }
+        bytes.extend_from_slice(&ext);
+        let original_ext = String::from_utf8(ext).map_err(|_| ContainerError::InvalidHeader("extension is not UTF-8"))?;
+        let header = ContainerHeader {
+            version: FORMAT_VERSION,
+            suite,
+            chunk_size,
+            key_id,
+            plaintext_len,
+            original_ext,
+            nonce_prefix,
+        };
+        Ok((header, bytes))
+    }
+}
+
+// Derives the short public identifier recorded in the header for a key
+pub fn key_id(key_bytes: &[u8; 32]) -> [u8; KEY_ID_LEN] {
+    let mut input = Vec::with_capacity(KEY_ID_CONTEXT.len() + key_bytes.len());
+    input.extend_from_slice(KEY_ID_CONTEXT);
+    input.extend_from_slice(key_bytes);
+    let hash = digest(&SHA256, &input);
+    *arrayref::array_ref![hash.as_ref(), 0, KEY_ID_LEN]
+}
+
+fn validate_chunk_size(chunk_size: u32) -> Result<(), ContainerError> {
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(ContainerError::InvalidHeader("chunk size out of range"));
+    }
+    Ok(())
+}
+
+fn chunk_nonce(prefix: &[u8; NONCE_PREFIX_LEN], counter: u32, last: bool) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&counter.to_be_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+// Reads until the buffer is full or the reader is exhausted, returning the bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+// Encrypts `plaintext_len` bytes from a stream into the chunked container
+pub fn encrypt_stream<R: Read, W: Write>(
+    reader: R,
+    mut writer: W,
+    key_bytes: &[u8; 32],
+    chunk_size: u32,
+    original_ext: &str,
+    plaintext_len: u64,
+) -> Result<ContainerHeader, ContainerError> {
+    validate_chunk_size(chunk_size)?;
+    if original_ext.len() > MAX_EXT_LEN {
+        return Err(ContainerError::InvalidHeader("extension too long"));
+    }
+    let mut reader = BufReader::new(reader);
+    let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+    SystemRandom::new().fill(&mut nonce_prefix).map_err(|_| ContainerError::Crypto)?;
+    let header = ContainerHeader {
+        version: FORMAT_VERSION,
+        suite: SUITE_AES_256_GCM_CHUNKED,
+        chunk_size,
+        key_id: key_id(key_bytes),
+        plaintext_len,
+        original_ext: original_ext.to_string(),
+        nonce_prefix,
+    };
+    let header_bytes = header.to_bytes();
+    writer.write_all(&header_bytes)?;
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key_bytes).map_err(|_| ContainerError::Crypto)?);
+
+    let mut buf = Zeroizing::new(vec![0u8; chunk_size as usize]);
+    let mut counter: u32 = 0;
+    let mut total: u64 = 0;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        let last = n < buf.len() || reader.fill_buf()?.is_empty();
+        let tag = key
+            .seal_in_place_separate_tag(chunk_nonce(&nonce_prefix, counter, last), Aad::from(&header_bytes), &mut buf[..n])
+            .map_err(|_| ContainerError::Crypto)?;
+        writer.write_all(&buf[..n])?;
+        writer.write_all(tag.as_ref())?;
+        total += n as u64;
+        if last {
+            break;
+        }
+        counter = counter.checked_add(1).ok_or(ContainerError::TooLarge)?;
+    }
+    if total != plaintext_len {
+        return Err(ContainerError::LengthMismatch { expected: plaintext_len, actual: total });
+    }
+    writer.flush()?;
+    Ok(header)
+}
+
+// Decrypts a chunked container stream, returning its authenticated header
+pub fn decrypt_stream<R: Read, W: Write>(reader: R, mut writer: W, key_bytes: &[u8; 32]) -> Result<ContainerHeader, ContainerError> {
+    let mut reader = BufReader::new(reader);
+    let (header, header_bytes) = ContainerHeader::read_from(&mut reader)?;
+    if header.key_id != key_id(key_bytes) {
+        return Err(ContainerError::KeyMismatch);
+    }
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key_bytes).map_err(|_| ContainerError::Crypto)?);
+
+    let mut buf = Zeroizing::new(vec![0u8; header.chunk_size as usize + TAG_LEN]);
+    let mut counter: u32 = 0;
+    let mut total: u64 = 0;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        if n < TAG_LEN {
+            return Err(ContainerError::Authentication { chunk: counter });
+        }
+        let last = n < buf.len() || reader.fill_buf()?.is_empty();
+        let (cipher_text, tag_bytes) = buf[..n].split_at_mut(n - TAG_LEN);
+        let tag: Tag = (&*tag_bytes).try_into().map_err(|_| ContainerError::Authentication { chunk: counter })?;
+        let plain_text = key
+            .open_in_place_separate_tag(chunk_nonce(&header.nonce_prefix, counter, last), Aad::from(&header_bytes), tag, cipher_text, 0..)
+            .map_err(|_| ContainerError::Authentication { chunk: counter })?;
+        writer.write_all(plain_text)?;
+        total += plain_text.len() as u64;
+        if last {
+            break;
+        }
+        counter = counter.checked_add(1).ok_or(ContainerError::TooLarge)?;
+    }
+    if total != header.plaintext_len {
+        return Err(ContainerError::LengthMismatch { expected: header.plaintext_len, actual: total });
+    }
+    writer.flush()?;
+    Ok(header)
+}
+
+// Generates a random 256-bit key that is wiped when dropped
+pub fn generate_key() -> Result<Zeroizing<[u8; 32]>, String> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    SystemRandom::new().fill(key.as_mut()).map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+// Encrypts a small record, such as a database field or a wrapped key, as
+// `nonce || ciphertext || tag` with a random nonce. `aad` binds the record to
+// where it is stored so it can't be moved to another row or column.
+pub fn seal(key_bytes: &[u8; 32], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key_bytes).map_err(|_| "Invalid key".to_string())?);
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(|e| e.to_string())?;
+    let mut sealed = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_LEN);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(plaintext);
+    let tag = key
+        .seal_in_place_separate_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut sealed[NONCE_LEN..])
+        .map_err(|_| "Encryption failed".to_string())?;
+    sealed.extend_from_slice(tag.as_ref());
+    Ok(sealed)
+}
+
+// Decrypts a record produced by `seal` with the same key and AAD. The plaintext
+// is decrypted in place and wiped when dropped.
+pub fn open(key_bytes: &[u8; 32], aad: &[u8], sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err("Encrypted record is truncated".to_string());
+    }
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key_bytes).map_err(|_| "Invalid key".to_string())?);
+    let (nonce, rest) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Invalid nonce".to_string())?;
+    let mut buf = Zeroizing::new(rest.to_vec());
+    let plain_len = key
+        .open_in_place(nonce, Aad::from(aad), &mut buf)
+        .map_err(|_| "Encrypted record failed authentication".to_string())?
+        .len();
+    buf.truncate(plain_len);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: u32 = 16;
+    const KEY: [u8; 32] = [7u8; 32];
+
+    fn encrypt(plaintext: &[u8]) -> Vec<u8> {
+        let mut container = Vec::new();
+        encrypt_stream(plaintext, &mut container, &KEY, CHUNK, "txt", plaintext.len() as u64).unwrap();
+        container
+    }
+
+    fn decrypt(container: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, ContainerError> {
+        let mut plaintext = Vec::new();
+        decrypt_stream(container, &mut plaintext, key)?;
+        Ok(plaintext)
+    }
+
+    fn header_len() -> usize {
+        FIXED_HEADER_LEN + "txt".len()
+    }
+
+    #[test]
+    fn round_trips_chunk_boundaries() {
+        for len in [0, 1, CHUNK as usize, 2 * CHUNK as usize, 2 * CHUNK as usize + 5] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let container = encrypt(&plaintext);
+            let chunks = len.div_ceil(CHUNK as usize).max(1);
+            assert_eq!(container.len(), header_len() + len + chunks * TAG_LEN, "length {}", len);
+            let mut decrypted = Vec::new();
+            let header = decrypt_stream(container.as_slice(), &mut decrypted, &KEY).unwrap();
+            assert_eq!(decrypted, plaintext);
+            assert_eq!(header.plaintext_len, len as u64);
+            assert_eq!(header.original_ext, "txt");
+        }
+    }
+
+    #[test]
+    fn rejects_a_truncated_stream() {
+        let container = encrypt(&[1u8; 3 * CHUNK as usize]);
+        // Dropping the last chunk leaves a stream whose new final chunk wasn't sealed as last
+        let truncated = &container[..container.len() - (CHUNK as usize + TAG_LEN)];
+        assert!(matches!(decrypt(truncated, &KEY), Err(ContainerError::Authentication { chunk: 1 })));
+        let cut_mid_chunk = &container[..container.len() - 1];
+        assert!(matches!(decrypt(cut_mid_chunk, &KEY), Err(ContainerError::Authentication { chunk: 2 })));
+    }
+
+    #[test]
+    fn rejects_reordered_chunks() {
+        let plaintext: Vec<u8> = (0..3 * CHUNK).map(|i| i as u8).collect();
+        let mut container = encrypt(&plaintext);
+        let sealed_chunk = CHUNK as usize + TAG_LEN;
+        let (first, second) = container[header_len()..].split_at_mut(sealed_chunk);
+        first.swap_with_slice(&mut second[..sealed_chunk]);
+        assert!(matches!(decrypt(&container, &KEY), Err(ContainerError::Authentication { chunk: 0 })));
+    }
+
+    #[test]
+    fn rejects_a_tampered_header() {
+        let container = encrypt(b"header fields are authenticated");
+        // plaintext_len, and the stored extension
+        for offset in [32, FIXED_HEADER_LEN] {
+            let mut tampered = container.clone();
+            tampered[offset] ^= 1;
+            assert!(matches!(decrypt(&tampered, &KEY), Err(ContainerError::Authentication { chunk: 0 })), "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn rejects_a_different_key() {
+        let container = encrypt(b"secret");
+        assert!(matches!(decrypt(&container, &[8u8; 32]), Err(ContainerError::KeyMismatch)));
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_suites() {
+        let container = encrypt(b"secret");
+        let mut version = container.clone();
+        version[4] = FORMAT_VERSION + 1;
+        assert!(matches!(decrypt(&version, &KEY), Err(ContainerError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1));
+        let mut suite = container.clone();
+        suite[5] = SUITE_AES_256_GCM_CHUNKED + 1;
+        assert!(matches!(decrypt(&suite, &KEY), Err(ContainerError::UnsupportedSuite(s)) if s == SUITE_AES_256_GCM_CHUNKED + 1));
+        let mut magic = container;
+        magic[0] = b'X';
+        assert!(matches!(decrypt(&magic, &KEY), Err(ContainerError::BadMagic)));
+    }
+
+    #[test]
+    fn sealed_records_are_bound_to_their_aad() {
+        let sealed = seal(&KEY, b"users.email", b"alice@example.com").unwrap();
+        assert_eq!(open(&KEY, b"users.email", &sealed).unwrap().as_slice(), b"alice@example.com");
+        assert!(open(&KEY, b"templates.mappings", &sealed).is_err());
+        assert!(open(&[8u8; 32], b"users.email", &sealed).is_err());
+    }
+}
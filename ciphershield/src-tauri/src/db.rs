@@ -1,49 +1,64 @@
-use rusqlite::{params, Connection, Result as SqlResult, OptionalExtension};
+use rusqlite::{params, params_from_iter, Connection, Result as SqlResult, OptionalExtension};
 use std::fs::create_dir_all;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tauri::{AppHandle, Manager};
 use tracing::info;
-use crate::models::{Template, MappingItem};
-
-// Opens and initializes a secure SQLite database in the app's local data directory
-pub fn get_secure_db(app_handle: &AppHandle) -> SqlResult<Connection> {
-    let data_dir = app_handle.path().app_local_data_dir().unwrap_or(PathBuf::from("."));
-    info!("Data directory: {:?}", data_dir);
-    create_dir_all(&data_dir).map_err(|_| rusqlite::Error::ExecuteReturnedResults)?;
-    let db_path = data_dir.join("secure.db");
-    info!("Opening DB at: {:?}", db_path);
-    let conn = Connection::open(&db_path)?;
-    init_schema(&conn)?;
-    info!("DB initialized");
-    Ok(conn)
-}
-
-// Creates the database schema with users and templates tables
-fn init_schema(conn: &Connection) -> SqlResult<()> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS users (
-            email TEXT PRIMARY KEY,
-            password_hash TEXT NOT NULL,
-            salt TEXT NOT NULL
-        );",
-        [],
-    )?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS templates (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            mappings TEXT NOT NULL,
-            custom_recognizers TEXT NOT NULL DEFAULT '[]'
-        );",
-        [],
-    )?;
-    Ok(())
+use crate::audit::{self, AuditEvent, ChainSeal};
+use crate::keystore::{self, DataKey};
+use crate::migrations;
+use crate::models::{Template, MappingItem, ProcessedFile, SecuritySettings, AuditEntry, AuditQuery};
+
+// Shared handle to secure.db, opened once at startup and kept in Tauri managed state.
+// Clones share the same connection, for work spawned off the command that started it.
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
 }
 
-// Inserts a new user into the users table with email, hashed password, and salt
+impl Database {
+    // Opens secure.db in the app's local data directory, configures it and applies pending migrations
+    pub fn open(app_handle: &AppHandle) -> SqlResult<Self> {
+        let data_dir = app_handle.path().app_local_data_dir().unwrap_or(PathBuf::from("."));
+        info!("Data directory: {:?}", data_dir);
+        create_dir_all(&data_dir).map_err(|_| rusqlite::Error::ExecuteReturnedResults)?;
+        let db_path = data_dir.join("secure.db");
+        info!("Opening DB at: {:?}", db_path);
+        let conn = Connection::open(&db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        // Overwrite deleted content so replaced plaintext rows don't linger in free pages
+        conn.pragma_update(None, "secure_delete", "ON")?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        migrations::migrate(&conn)?;
+        info!("DB initialized");
+        Ok(Database { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    // Runs blocking database work on the blocking thread pool so commands don't stall the async runtime
+    pub async fn run<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&Connection) -> Result<T, String> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = Arc::clone(&self.conn);
+        tauri::async_runtime::spawn_blocking(move || {
+            // A panic in earlier work rolls back its transaction, so the connection is still usable
+            let conn = conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            f(&conn)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+}
+
+// Inserts a new user into the users table; PHC hashes embed their salt and store an empty salt column.
+// The first account registered on an install is its administrator.
 pub fn insert_user(conn: &Connection, email: &str, hash: &str, salt: &str) -> SqlResult<()> {
     conn.execute(
-        "INSERT OR IGNORE INTO users (email, password_hash, salt) VALUES (?1, ?2, ?3)",
+        "INSERT OR IGNORE INTO users (email, password_hash, salt, is_admin)
+         VALUES (?1, ?2, ?3, NOT EXISTS (SELECT 1 FROM users))",
         params![email, hash, salt],
     )?;
     Ok(())
@@ -59,27 +74,519 @@ pub fn get_user_hash_salt(conn: &Connection, email: &str) -> SqlResult<Option<(S
     .optional()
 }
 
-// Inserts a new template into the templates table with name, mappings, and custom recognizers
-pub fn insert_template(conn: &Connection, name: &str, mappings_json: &str, custom_recognizers_json: &str) -> SqlResult<i32> {
+// Finds the stored form of a user's email ignoring case. Accounts registered before emails
+// were normalized can be stored in mixed case; if several differ only in case, the one
+// matching `entered` exactly wins, then the lowercase one.
+pub fn find_user_email(conn: &Connection, entered: &str, normalized: &str) -> SqlResult<Option<String>> {
+    conn.query_row(
+        "SELECT email FROM users WHERE email = ?1 COLLATE NOCASE
+         ORDER BY email = ?1 DESC, email = ?2 DESC, rowid LIMIT 1",
+        params![entered, normalized],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+// Replaces the password hash and salt for an existing user, e.g. after a rehash on login
+pub fn update_user_password(conn: &Connection, email: &str, hash: &str, salt: &str) -> SqlResult<usize> {
     conn.execute(
-        "INSERT INTO templates (name, mappings, custom_recognizers) VALUES (?1, ?2, ?3)",
-        params![name, mappings_json, custom_recognizers_json],
+        "UPDATE users SET password_hash = ?1, salt = ?2 WHERE email = ?3",
+        params![hash, salt, email],
+    )
+}
+
+// Whether a user holds the administrator role
+pub fn is_admin(conn: &Connection, email: &str) -> SqlResult<bool> {
+    conn.query_row("SELECT is_admin FROM users WHERE email = ?1", params![email], |row| row.get(0))
+        .optional()
+        .map(|admin| admin.unwrap_or(false))
+}
+
+// Deletes a user from the users table. If that removes the last administrator, the
+// oldest remaining account takes over the role so settings can still be managed.
+pub fn delete_user(conn: &Connection, email: &str) -> SqlResult<usize> {
+    let deleted = conn.execute("DELETE FROM users WHERE email = ?1", params![email])?;
+    conn.execute(
+        "UPDATE users SET is_admin = 1
+         WHERE rowid = (SELECT MIN(rowid) FROM users) AND NOT EXISTS (SELECT 1 FROM users WHERE is_admin = 1)",
+        [],
+    )?;
+    Ok(deleted)
+}
+
+// Retrieves the KDF salt and wrapped data-encryption key for a user, if one has been created
+pub fn get_user_key(conn: &Connection, email: &str) -> SqlResult<Option<(String, String)>> {
+    conn.query_row(
+        "SELECT kdf_salt, wrapped_key FROM user_keys WHERE email = ?1",
+        params![email],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+// Stores a user's wrapped data-encryption key, replacing any previous wrapping
+pub fn save_user_key(conn: &Connection, email: &str, kdf_salt: &str, wrapped_key: &str) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO user_keys (email, kdf_salt, wrapped_key) VALUES (?1, ?2, ?3)
+         ON CONFLICT(email) DO UPDATE SET kdf_salt = excluded.kdf_salt, wrapped_key = excluded.wrapped_key",
+        params![email, kdf_salt, wrapped_key],
+    )?;
+    Ok(())
+}
+
+// Deletes a user's wrapped data-encryption key
+pub fn delete_user_key(conn: &Connection, email: &str) -> SqlResult<()> {
+    conn.execute("DELETE FROM user_keys WHERE email = ?1", params![email])?;
+    Ok(())
+}
+
+// Retrieves the consecutive failed login count and lockout expiry (unix seconds) for an email
+pub fn get_login_attempts(conn: &Connection, email: &str) -> SqlResult<(u32, Option<i64>)> {
+    Ok(conn
+        .query_row(
+            "SELECT failed_count, locked_until FROM login_attempts WHERE email = ?1",
+            params![email],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .unwrap_or((0, None)))
+}
+
+// Records a failed login, storing the new failure count and any lockout expiry
+pub fn record_failed_login(conn: &Connection, email: &str, failed_count: u32, locked_until: Option<i64>, now: i64) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO login_attempts (email, failed_count, locked_until, last_failed_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(email) DO UPDATE SET failed_count = excluded.failed_count,
+             locked_until = excluded.locked_until, last_failed_at = excluded.last_failed_at",
+        params![email, failed_count, locked_until, now],
+    )?;
+    Ok(())
+}
+
+// Resets the failed login counter after a successful login
+pub fn clear_login_attempts(conn: &Connection, email: &str) -> SqlResult<()> {
+    conn.execute("DELETE FROM login_attempts WHERE email = ?1", params![email])?;
+    Ok(())
+}
+
+// Retrieves a user's TOTP secret (hex), whether it is enabled, and the last accepted time step
+pub fn get_mfa(conn: &Connection, email: &str) -> SqlResult<Option<(String, bool, Option<i64>)>> {
+    conn.query_row(
+        "SELECT secret, enabled, last_used_step FROM mfa WHERE email = ?1",
+        params![email],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+    .optional()
+}
+
+// Stores a new, not yet enabled TOTP secret and replaces the user's recovery code hashes
+pub fn save_mfa_enrollment(conn: &Connection, email: &str, secret: &str, recovery_code_hashes: &[String]) -> SqlResult<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO mfa (email, secret, enabled, last_used_step) VALUES (?1, ?2, 0, NULL)
+         ON CONFLICT(email) DO UPDATE SET secret = excluded.secret, enabled = 0, last_used_step = NULL",
+        params![email, secret],
+    )?;
+    tx.execute("DELETE FROM mfa_recovery_codes WHERE email = ?1", params![email])?;
+    for hash in recovery_code_hashes {
+        tx.execute(
+            "INSERT INTO mfa_recovery_codes (email, code_hash) VALUES (?1, ?2)",
+            params![email, hash],
+        )?;
+    }
+    tx.commit()
+}
+
+// Enables MFA once the user has proven their authenticator works
+pub fn enable_mfa(conn: &Connection, email: &str, last_used_step: i64) -> SqlResult<usize> {
+    conn.execute(
+        "UPDATE mfa SET enabled = 1, last_used_step = ?1 WHERE email = ?2",
+        params![last_used_step, email],
+    )
+}
+
+// Records the last accepted TOTP time step so the same code can't be used twice
+pub fn set_mfa_last_used_step(conn: &Connection, email: &str, step: i64) -> SqlResult<usize> {
+    conn.execute(
+        "UPDATE mfa SET last_used_step = ?1 WHERE email = ?2",
+        params![step, email],
+    )
+}
+
+// Marks a recovery code as used, returning false if it doesn't exist or was already used
+pub fn use_recovery_code(conn: &Connection, email: &str, code_hash: &str, now: i64) -> SqlResult<bool> {
+    let updated = conn.execute(
+        "UPDATE mfa_recovery_codes SET used_at = ?1 WHERE email = ?2 AND code_hash = ?3 AND used_at IS NULL",
+        params![now, email, code_hash],
+    )?;
+    Ok(updated > 0)
+}
+
+// Removes a user's TOTP secret and recovery codes
+pub fn delete_mfa(conn: &Connection, email: &str) -> SqlResult<()> {
+    conn.execute("DELETE FROM mfa WHERE email = ?1", params![email])?;
+    conn.execute("DELETE FROM mfa_recovery_codes WHERE email = ?1", params![email])?;
+    Ok(())
+}
+
+// Loads the security settings, falling back to defaults for missing or unknown values
+pub fn get_security_settings(conn: &Connection) -> SqlResult<SecuritySettings> {
+    let value: Option<String> = conn
+        .query_row("SELECT value FROM settings WHERE key = 'security'", [], |row| row.get(0))
+        .optional()?;
+    Ok(value.and_then(|v| serde_json::from_str(&v).ok()).unwrap_or_default())
+}
+
+// Stores the security settings as a JSON document
+pub fn save_security_settings(conn: &Connection, settings: &SecuritySettings) -> SqlResult<()> {
+    let value = serde_json::to_string(settings).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES ('security', ?1)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![value],
+    )?;
+    Ok(())
+}
+
+fn template_context(owner: &str, column: &str) -> String {
+    format!("templates:{}:{}", owner, column)
+}
+
+fn encrypt_error(e: String) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(e.into())
+}
+
+fn decrypt_error(column: usize, e: String) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, e.into())
+}
+
+// Inserts a new template owned by a user, encrypting its mappings and custom recognizers
+pub fn insert_template(conn: &Connection, owner: &str, key: &DataKey, name: &str, mappings_json: &str, custom_recognizers_json: &str, engine: &str) -> SqlResult<i32> {
+    let mappings = keystore::encrypt_field(key, &template_context(owner, "mappings"), mappings_json).map_err(encrypt_error)?;
+    let custom_recognizers = keystore::encrypt_field(key, &template_context(owner, "custom_recognizers"), custom_recognizers_json).map_err(encrypt_error)?;
+    conn.execute(
+        "INSERT INTO templates (name, mappings, custom_recognizers, owner, engine) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![name, mappings, custom_recognizers, owner, engine],
     )?;
     Ok(conn.last_insert_rowid() as i32)
 }
 
-// Retrieves all templates from the templates table
-pub fn get_templates(conn: &Connection) -> SqlResult<Vec<Template>> {
-    let mut stmt = conn.prepare("SELECT id, name, mappings, custom_recognizers FROM templates")?;
-    let templates = stmt.query_map([], |row| {
+// Retrieves and decrypts all templates owned by a user
+pub fn get_templates(conn: &Connection, owner: &str, key: &DataKey) -> SqlResult<Vec<Template>> {
+    let mut stmt = conn.prepare("SELECT id, name, mappings, custom_recognizers, engine FROM templates WHERE owner = ?1")?;
+    let templates = stmt.query_map(params![owner], |row| {
         let id: i32 = row.get(0)?;
         let name: String = row.get(1)?;
-        let mappings_json: String = row.get(2)?;
-        let custom_recognizers_json: String = row.get(3)?;
+        let mappings_json = keystore::decrypt_field(key, &template_context(owner, "mappings"), &row.get::<_, String>(2)?)
+            .map_err(|e| decrypt_error(2, e))?;
+        let custom_recognizers_json = keystore::decrypt_field(key, &template_context(owner, "custom_recognizers"), &row.get::<_, String>(3)?)
+            .map_err(|e| decrypt_error(3, e))?;
         let mappings: Vec<MappingItem> = serde_json::from_str(&mappings_json).unwrap_or_default();
         let custom_recognizers: Vec<crate::models::CustomRecognizer> = serde_json::from_str(&custom_recognizers_json).unwrap_or_default();
-        Ok(Template { id, name, mappings, custom_recognizers })
+        let engine: Option<String> = row.get(4)?;
+        Ok(Template { id, name, mappings, custom_recognizers, engine })
     })?
     .collect::<Result<_, _>>()?;
     Ok(templates)
-}
\ No newline at end of file
+}
+
+// Retrieves and decrypts the mappings of one template owned by a user
+pub fn get_template_mappings(conn: &Connection, id: i32, owner: &str, key: &DataKey) -> SqlResult<Option<Vec<MappingItem>>> {
+    let stored: Option<String> = conn
+        .query_row(
+            "SELECT mappings FROM templates WHERE id = ?1 AND owner = ?2",
+            params![id, owner],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(stored) = stored else {
+        return Ok(None);
+    };
+    let mappings_json = keystore::decrypt_field(key, &template_context(owner, "mappings"), &stored).map_err(|e| decrypt_error(0, e))?;
+    let mappings = serde_json::from_str(&mappings_json).map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?;
+    Ok(Some(mappings))
+}
+
+// Retrieves the engine a template owned by a user was made with, if it recorded one
+pub fn get_template_engine(conn: &Connection, id: i32, owner: &str) -> SqlResult<Option<String>> {
+    let engine: Option<Option<String>> = conn
+        .query_row("SELECT engine FROM templates WHERE id = ?1 AND owner = ?2", params![id, owner], |row| row.get(0))
+        .optional()?;
+    Ok(engine.flatten())
+}
+
+// Encrypts templates saved before per-user encryption and assigns them to the given user.
+// Only done on a single-user install, where they can only have been that user's; with
+// several accounts there's no telling whose they were, so they stay unowned and unused.
+pub fn claim_legacy_templates(conn: &Connection, owner: &str, key: &DataKey) -> SqlResult<usize> {
+    let users: i64 = conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))?;
+    if users != 1 {
+        return Ok(0);
+    }
+    let tx = conn.unchecked_transaction()?;
+    let legacy: Vec<(i32, String, String)> = tx
+        .prepare("SELECT id, mappings, custom_recognizers FROM templates WHERE owner IS NULL")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+    for (id, mappings_json, custom_recognizers_json) in &legacy {
+        let mappings = keystore::encrypt_field(key, &template_context(owner, "mappings"), mappings_json).map_err(encrypt_error)?;
+        let custom_recognizers = keystore::encrypt_field(key, &template_context(owner, "custom_recognizers"), custom_recognizers_json).map_err(encrypt_error)?;
+        tx.execute(
+            "UPDATE templates SET mappings = ?1, custom_recognizers = ?2, owner = ?3 WHERE id = ?4",
+            params![mappings, custom_recognizers, owner, id],
+        )?;
+    }
+    tx.commit()?;
+    Ok(legacy.len())
+}
+
+// Deletes all templates owned by a user
+pub fn delete_user_templates(conn: &Connection, owner: &str) -> SqlResult<usize> {
+    conn.execute("DELETE FROM templates WHERE owner = ?1", params![owner])
+}
+
+// Inserts a processed file record owned by a user, returning its id
+pub fn insert_processed_file(conn: &Connection, owner: &str, file: &ProcessedFile) -> SqlResult<i64> {
+    conn.execute(
+        "INSERT INTO processed_files (owner, name, source_path, path, action, template_id, pii_count, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            owner,
+            file.name,
+            file.source_path,
+            file.output_path,
+            file.action,
+            file.template_id,
+            file.pii_count,
+            file.timestamp
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn processed_file_from_row(row: &rusqlite::Row) -> SqlResult<ProcessedFile> {
+    Ok(ProcessedFile {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        source_path: row.get(2)?,
+        output_path: row.get(3)?,
+        action: row.get(4)?,
+        template_id: row.get(5)?,
+        pii_count: row.get(6)?,
+        timestamp: row.get(7)?,
+    })
+}
+
+// Retrieves a user's processed files, newest first
+pub fn get_processed_files(conn: &Connection, owner: &str) -> SqlResult<Vec<ProcessedFile>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, source_path, path, action, template_id, pii_count, timestamp
+         FROM processed_files WHERE owner = ?1 ORDER BY id DESC",
+    )?;
+    let files = stmt.query_map(params![owner], processed_file_from_row)?
+        .collect::<Result<_, _>>()?;
+    Ok(files)
+}
+
+// Retrieves one of a user's processed files
+pub fn get_processed_file(conn: &Connection, owner: &str, id: i64) -> SqlResult<Option<ProcessedFile>> {
+    conn.query_row(
+        "SELECT id, name, source_path, path, action, template_id, pii_count, timestamp
+         FROM processed_files WHERE id = ?1 AND owner = ?2",
+        params![id, owner],
+        processed_file_from_row,
+    )
+    .optional()
+}
+
+// Renames one of a user's processed file records, returning false if it doesn't exist
+pub fn rename_processed_file(conn: &Connection, owner: &str, id: i64, new_name: &str) -> SqlResult<bool> {
+    let updated = conn.execute(
+        "UPDATE processed_files SET name = ?1 WHERE id = ?2 AND owner = ?3",
+        params![new_name, id, owner],
+    )?;
+    Ok(updated > 0)
+}
+
+// Deletes one of a user's processed file records, returning false if it doesn't exist
+pub fn delete_processed_file(conn: &Connection, owner: &str, id: i64) -> SqlResult<bool> {
+    let deleted = conn.execute("DELETE FROM processed_files WHERE id = ?1 AND owner = ?2", params![id, owner])?;
+    Ok(deleted > 0)
+}
+
+// Deletes all processed file records owned by a user
+pub fn delete_user_processed_files(conn: &Connection, owner: &str) -> SqlResult<usize> {
+    conn.execute("DELETE FROM processed_files WHERE owner = ?1", params![owner])
+}
+
+const AUDIT_DEFAULT_PAGE_SIZE: u32 = 50;
+const AUDIT_MAX_PAGE_SIZE: u32 = 500;
+
+// Appends a sealed entry to the audit_log table
+pub fn insert_audit_entry(conn: &Connection, timestamp: &str, event: &AuditEvent, seal: &ChainSeal) -> SqlResult<i64> {
+    conn.execute(
+        "INSERT INTO audit_log (timestamp, level, operation, user, source, status, duration_ms, pii_count, details, prev_hash, entry_hash, mac)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            timestamp,
+            event.level,
+            event.operation,
+            event.user,
+            event.source,
+            event.status,
+            event.duration_ms,
+            event.pii_count,
+            event.details,
+            seal.prev_hash,
+            seal.entry_hash,
+            seal.mac
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// Retrieves the hash of the newest sealed audit entry, which the next entry links to
+pub fn last_audit_hash(conn: &Connection) -> SqlResult<Option<String>> {
+    Ok(conn
+        .query_row(
+            "SELECT entry_hash FROM audit_log WHERE entry_hash IS NOT NULL ORDER BY id DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten())
+}
+
+// Stores the chain seal of an existing entry; only used when sealing entries written before chaining
+pub fn set_audit_seal(conn: &Connection, id: i64, seal: &ChainSeal) -> SqlResult<()> {
+    conn.execute(
+        "UPDATE audit_log SET prev_hash = ?1, entry_hash = ?2, mac = ?3 WHERE id = ?4",
+        params![seal.prev_hash, seal.entry_hash, seal.mac, id],
+    )?;
+    Ok(())
+}
+
+// Retrieves every audit entry in chain order with its seal, if it has one
+pub fn get_audit_chain(conn: &Connection, only_unsealed: bool) -> SqlResult<Vec<(AuditEntry, Option<ChainSeal>)>> {
+    let filter = if only_unsealed { " WHERE entry_hash IS NULL" } else { "" };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, timestamp, level, operation, user, source, status, duration_ms, pii_count, details, prev_hash, entry_hash, mac
+         FROM audit_log{} ORDER BY id",
+        filter
+    ))?;
+    let rows = stmt
+        .query_map([], |row| {
+            let entry = audit_entry_from_row(row)?;
+            let prev_hash: Option<String> = row.get(10)?;
+            let entry_hash: Option<String> = row.get(11)?;
+            let seal = match (prev_hash, entry_hash) {
+                (Some(prev_hash), Some(entry_hash)) => Some(ChainSeal { prev_hash, entry_hash, mac: row.get(12)? }),
+                _ => None,
+            };
+            Ok((entry, seal))
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+// Returns the highest id ever assigned to an audit entry, which outlives deleted rows
+pub fn audit_log_sequence(conn: &Connection) -> SqlResult<Option<i64>> {
+    conn.query_row("SELECT seq FROM sqlite_sequence WHERE name = 'audit_log'", [], |row| row.get(0))
+        .optional()
+}
+
+fn audit_entry_from_row(row: &rusqlite::Row) -> SqlResult<AuditEntry> {
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        level: row.get(2)?,
+        operation: row.get(3)?,
+        user: row.get(4)?,
+        source: row.get(5)?,
+        status: row.get(6)?,
+        duration_ms: row.get(7)?,
+        pii_count: row.get(8)?,
+        details: row.get(9)?,
+    })
+}
+
+// Escapes LIKE wildcards so search terms match literally
+fn like_pattern(term: &str) -> String {
+    let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+// Builds the WHERE clause and parameters for the filters in an audit query
+fn audit_filter(query: &AuditQuery) -> Result<(String, Vec<String>), String> {
+    let mut clauses: Vec<&str> = Vec::new();
+    let mut values: Vec<String> = Vec::new();
+    for (clause, value) in [
+        ("level = ?", &query.level),
+        ("operation = ?", &query.operation),
+        ("status = ?", &query.status),
+        ("user = ?", &query.user),
+    ] {
+        if let Some(value) = value.as_ref().filter(|v| !v.is_empty()) {
+            clauses.push(clause);
+            values.push(value.clone());
+        }
+    }
+    if let Some(search) = query.search.as_ref().filter(|s| !s.is_empty()) {
+        clauses.push("(user LIKE ? ESCAPE '\\' OR source LIKE ? ESCAPE '\\' OR details LIKE ? ESCAPE '\\')");
+        let pattern = like_pattern(search);
+        values.extend([pattern.clone(), pattern.clone(), pattern]);
+    }
+    if let Some(from) = query.from.as_ref().filter(|s| !s.is_empty()) {
+        clauses.push("timestamp >= ?");
+        values.push(audit::parse_timestamp(from)?);
+    }
+    if let Some(to) = query.to.as_ref().filter(|s| !s.is_empty()) {
+        clauses.push("timestamp <= ?");
+        values.push(audit::parse_timestamp(to)?);
+    }
+    let where_sql = if clauses.is_empty() { String::new() } else { format!(" WHERE {}", clauses.join(" AND ")) };
+    Ok((where_sql, values))
+}
+
+// Retrieves one page of audit entries matching the query, newest first, with the total match count
+pub fn query_audit_log(conn: &Connection, query: &AuditQuery) -> Result<(Vec<AuditEntry>, i64), String> {
+    let (where_sql, values) = audit_filter(query)?;
+    let total: i64 = conn
+        .query_row(&format!("SELECT COUNT(*) FROM audit_log{}", where_sql), params_from_iter(&values), |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+
+    let page_size = match query.page_size {
+        0 => AUDIT_DEFAULT_PAGE_SIZE,
+        n => n.min(AUDIT_MAX_PAGE_SIZE),
+    };
+    let offset = i64::from(query.page) * i64::from(page_size);
+    let sql = format!(
+        "SELECT id, timestamp, level, operation, user, source, status, duration_ms, pii_count, details
+         FROM audit_log{} ORDER BY id DESC LIMIT {} OFFSET {}",
+        where_sql, page_size, offset
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let entries = stmt
+        .query_map(params_from_iter(&values), audit_entry_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok((entries, total))
+}
+
+// Retrieves every audit entry matching the query's filters, oldest first, ignoring paging
+pub fn export_audit_log(conn: &Connection, query: &AuditQuery) -> Result<Vec<AuditEntry>, String> {
+    let (where_sql, values) = audit_filter(query)?;
+    let sql = format!(
+        "SELECT id, timestamp, level, operation, user, source, status, duration_ms, pii_count, details
+         FROM audit_log{} ORDER BY id",
+        where_sql
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let entries = stmt
+        .query_map(params_from_iter(&values), audit_entry_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(entries)
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::Serialize;
+use tauri::{command, State};
+use crate::jobs::{CancelToken, FileProgress};
+use crate::models::{CustomRecognizer, MappingItem, OutputPolicy, SecuritySettings};
+use crate::session::SessionState;
+
+// PII detection backends. Processing only reaches an engine through `PiiEngine`, so the
+// Python sidecar is one implementation and a native engine, a remote service or a test
+// double can sit beside it. Each job resolves its engine by id: the one the job asks for,
+// else the one its template was made with, else the default.
+
+pub const DEFAULT_ENGINE: &str = "sidecar";
+
+// What an engine can do, listed for the frontend and checked before a job is sent to it
+#[derive(Serialize, Clone)]
+pub struct EngineCapabilities {
+    pub id: String,
+    pub name: String,
+    // File extensions it reads, lowercase and without the dot
+    pub formats: Vec<String>,
+    pub custom_recognizers: bool,
+    pub analyze: bool,
+}
+
+impl EngineCapabilities {
+    pub fn supports_format(&self, ext: &str) -> bool {
+        self.formats.iter().any(|format| format.eq_ignore_ascii_case(ext))
+    }
+}
+
+// The document an engine works on
+pub enum Document<'a> {
+    // Text held in memory; the result comes back in memory
+    Text(&'a str),
+    // A file on disk; the result is written at `planned` under the output policy
+    File {
+        input_path: PathBuf,
+        planned: &'a Path,
+        policy: &'a OutputPolicy,
+        progress: &'a FileProgress,
+    },
+}
+
+pub struct EngineRequest<'a> {
+    pub document: Document<'a>,
+    // Replacements to reuse when anonymizing, or to reverse when deanonymizing
+    pub mappings: &'a [MappingItem],
+    pub custom_recognizers: &'a [CustomRecognizer],
+    pub settings: &'a SecuritySettings,
+    pub cancel: &'a CancelToken,
+}
+
+pub enum Processed {
+    Text(String),
+    // Where the result was written; None if the output policy skipped an existing file
+    File(Option<PathBuf>),
+}
+
+pub struct EngineOutput {
+    pub processed: Processed,
+    // The PII found, each with the replacement used for it
+    pub items: Vec<MappingItem>,
+}
+
+#[async_trait]
+pub trait PiiEngine: Send + Sync {
+    fn capabilities(&self) -> EngineCapabilities;
+
+    // Extension of the result for an input of the given format; by default the format is kept
+    fn output_format(&self, ext: &str) -> String {
+        ext.to_string()
+    }
+
+    // Finds PII without producing a result document
+    async fn analyze(&self, request: EngineRequest<'_>) -> Result<Vec<MappingItem>, String>;
+
+    async fn anonymize(&self, request: EngineRequest<'_>) -> Result<EngineOutput, String>;
+
+    // Puts the originals in `request.mappings` back in place of their replacements
+    async fn deanonymize(&self, request: EngineRequest<'_>) -> Result<EngineOutput, String>;
+}
+
+// Engines jobs can choose from, kept in Tauri managed state
+#[derive(Default)]
+pub struct EngineRegistry {
+    engines: BTreeMap<String, Arc<dyn PiiEngine>>,
+}
+
+impl EngineRegistry {
+    pub fn register(&mut self, engine: Arc<dyn PiiEngine>) {
+        self.engines.insert(engine.capabilities().id, engine);
+    }
+
+    // Resolves a job's engine from its own choice, then its template's, then the default
+    pub fn select(&self, job: Option<&str>, template: Option<&str>) -> Result<Arc<dyn PiiEngine>, String> {
+        let id = job.or(template).unwrap_or(DEFAULT_ENGINE);
+        self.engines.get(id).cloned().ok_or_else(|| format!("Unknown PII engine: {}", id))
+    }
+}
+
+// Runs an action on an engine after checking the engine can handle the request.
+// "analyze" returns text unchanged with the PII found; files can't be analyzed this way
+// since the result would have nowhere to go.
+pub async fn run(engine: &dyn PiiEngine, action: &str, request: EngineRequest<'_>) -> Result<EngineOutput, String> {
+    let capabilities = engine.capabilities();
+    if let Document::File { input_path, .. } = &request.document {
+        let ext = input_path.extension().and_then(|os| os.to_str()).unwrap_or("");
+        if !capabilities.supports_format(ext) {
+            return Err(format!("{} can't process .{} files", capabilities.name, ext));
+        }
+    }
+    if !request.custom_recognizers.is_empty() && !capabilities.custom_recognizers {
+        return Err(format!("{} doesn't support custom recognizers", capabilities.name));
+    }
+    match action {
+        "anonymize" => engine.anonymize(request).await,
+        "deanonymize" => engine.deanonymize(request).await,
+        "analyze" if capabilities.analyze => match request.document {
+            Document::Text(text) => {
+                let text = text.to_string();
+                let items = engine.analyze(request).await?;
+                Ok(EngineOutput { processed: Processed::Text(text), items })
+            }
+            Document::File { .. } => Err("Files can only be anonymized or deanonymized".to_string()),
+        },
+        other => Err(format!("{} doesn't support the {} action", capabilities.name, other)),
+    }
+}
+
+// Lists the engines a job or template can use
+#[command]
+pub async fn list_engines(session: State<'_, SessionState>, token: String, engines: State<'_, EngineRegistry>) -> Result<Vec<EngineCapabilities>, String> {
+    session.require(&token)?;
+    Ok(engines.engines.values().map(|engine| engine.capabilities()).collect())
+}
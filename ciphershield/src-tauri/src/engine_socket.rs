@@ -0,0 +1,135 @@
+use std::io::{self, BufWriter, Read, Write};
+use std::os::unix::net::UnixStream;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+use crate::models::{CustomRecognizer, MappingItem};
+
+// Framed protocol spoken over the engine's Unix socket. A frame is a 4-byte big-endian
+// length followed by that many bytes. A request is a JSON header frame, then the document
+// in content frames ending with an empty frame; the response is a JSON header frame and,
+// on success, the processed document framed the same way. Content only exists in memory on
+// either side, so no job key or intermediate file is involved.
+
+// Largest frame either side accepts, so a bad length can't force a huge allocation
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Serialize)]
+pub struct SocketRequest<'a> {
+    // The engine's per-launch secret
+    pub secret: &'a str,
+    pub action: &'a str,
+    pub mappings: &'a [MappingItem],
+    pub original_ext: &'a str,
+    // Size of the document that follows, which the engine checks against its limit
+    pub plaintext_len: u64,
+    pub custom_recognizers: &'a [CustomRecognizer],
+}
+
+#[derive(Deserialize)]
+struct SocketResponse {
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    items: Vec<MappingItem>,
+    #[serde(default)]
+    content_length: u64,
+}
+
+// Header of a successful response; the processed document follows on the stream
+pub struct ResponseHeader {
+    pub items: Vec<MappingItem>,
+    pub content_length: u64,
+}
+
+fn write_frame<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(data)
+}
+
+fn read_frame_len<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Engine frame of {} bytes exceeds the limit", len)));
+    }
+    Ok(len)
+}
+
+// Serializes a request header; wiped after use since it carries the secret and mappings
+pub fn encode_request(request: &SocketRequest) -> Result<Zeroizing<Vec<u8>>, String> {
+    Ok(Zeroizing::new(serde_json::to_vec(request).map_err(|e| e.to_string())?))
+}
+
+// Sends a request header followed by the document read from `content`. If the engine stops
+// reading part way, as it does when refusing an oversize document, its error is returned.
+pub fn send_request<R: Read>(stream: &UnixStream, header: &[u8], content: R, chunk_size: usize) -> Result<(), String> {
+    write_request(stream, header, content, chunk_size).map_err(|e| match e.kind() {
+        io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset => read_response(stream).err().unwrap_or_else(|| e.to_string()),
+        _ => e.to_string(),
+    })
+}
+
+fn write_request<R: Read>(stream: &UnixStream, header: &[u8], mut content: R, chunk_size: usize) -> io::Result<()> {
+    let mut writer = BufWriter::new(stream);
+    write_frame(&mut writer, header)?;
+    let mut buf = Zeroizing::new(vec![0u8; chunk_size]);
+    loop {
+        let n = match content.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        write_frame(&mut writer, &buf[..n])?;
+    }
+    write_frame(&mut writer, &[])?;
+    writer.flush()
+}
+
+// Reads the response header, failing with the engine's error if it reported one
+pub fn read_response(mut stream: &UnixStream) -> Result<ResponseHeader, String> {
+    let len = read_frame_len(&mut stream).map_err(|e| e.to_string())?;
+    let mut header = Zeroizing::new(vec![0u8; len]);
+    stream.read_exact(&mut header).map_err(|e| e.to_string())?;
+    let response: SocketResponse = serde_json::from_slice(&header).map_err(|e| e.to_string())?;
+    match response.error {
+        Some(error) => Err(error),
+        None => Ok(ResponseHeader { items: response.items, content_length: response.content_length }),
+    }
+}
+
+// Reads the framed document that follows a response header, ending at the empty frame
+pub struct ContentReader<'a> {
+    stream: &'a UnixStream,
+    remaining: usize,
+    done: bool,
+}
+
+impl<'a> ContentReader<'a> {
+    pub fn new(stream: &'a UnixStream) -> Self {
+        ContentReader { stream, remaining: 0, done: false }
+    }
+}
+
+impl Read for ContentReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            self.remaining = read_frame_len(&mut self.stream)?;
+            if self.remaining == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+        }
+        let max = buf.len().min(self.remaining);
+        let n = self.stream.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Engine closed the connection mid-frame"));
+        }
+        self.remaining -= n;
+        Ok(n)
+    }
+}
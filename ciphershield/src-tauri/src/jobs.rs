@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::watch;
+use tracing::warn;
+use uuid::Uuid;
+use crate::models::{FileStatus, ProcessOutput};
+
+// File processing runs in the background: `process_files` returns a job id straight away,
+// progress is pushed to the frontend as events while the job runs, and the result arrives
+// in a final event. A job can be cancelled until then; running files stop at their next
+// chunk or abandon their engine request, and their workspaces are removed as they unwind.
+
+pub const JOB_PROGRESS_EVENT: &str = "job-progress";
+pub const JOB_FINISHED_EVENT: &str = "job-finished";
+pub const CANCELLED: &str = "Cancelled";
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStage {
+    Encrypting,
+    // Streaming the document to the engine over its socket
+    Sending,
+    Analyzing,
+    Decrypting,
+    // Streaming the result back from the engine's socket
+    Receiving,
+    Done,
+}
+
+impl JobStage {
+    // Share of a file's overall progress covered by each stage, as (start, end) percent
+    fn range(self) -> (u64, u64) {
+        match self {
+            JobStage::Encrypting | JobStage::Sending => (0, 40),
+            JobStage::Analyzing => (40, 60),
+            JobStage::Decrypting | JobStage::Receiving => (60, 100),
+            JobStage::Done => (100, 100),
+        }
+    }
+}
+
+// Payload of the event sent as a file moves through a job
+#[derive(Serialize, Clone)]
+pub struct JobProgress {
+    pub job_id: String,
+    pub input_path: String,
+    pub stage: JobStage,
+    pub bytes_processed: u64,
+    pub bytes_total: u64,
+    pub percent: u8,
+    // How the file ended, set on the `done` stage only
+    pub status: Option<FileStatus>,
+}
+
+// Payload of the event sent once when a job ends; `output` is missing only if the job
+// failed as a whole
+#[derive(Serialize, Clone)]
+pub struct JobFinished {
+    pub job_id: String,
+    pub cancelled: bool,
+    pub output: Option<ProcessOutput>,
+    pub error: Option<String>,
+}
+
+// Lets the tasks of one job see whether it has been cancelled
+#[derive(Clone)]
+pub struct CancelToken(watch::Receiver<bool>);
+
+impl CancelToken {
+    // A token for work outside any job, which is never cancelled
+    pub fn never() -> Self {
+        CancelToken(watch::channel(false).1)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    // Completes once the job is cancelled
+    pub async fn cancelled(&self) {
+        let mut rx = self.0.clone();
+        if rx.wait_for(|cancelled| *cancelled).await.is_err() {
+            // The job already finished, so it can no longer be cancelled
+            std::future::pending::<()>().await;
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err(CANCELLED.to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Jobs still running, kept in Tauri managed state
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, watch::Sender<bool>>>,
+}
+
+impl JobRegistry {
+    // Registers a new job and returns its id with the token its tasks should watch
+    pub fn start(&self) -> (String, CancelToken) {
+        let id = Uuid::new_v4().to_string();
+        let (tx, rx) = watch::channel(false);
+        self.jobs.lock().unwrap().insert(id.clone(), tx);
+        (id, CancelToken(rx))
+    }
+
+    // Signals a running job to stop; false if no such job is running
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(id) {
+            Some(tx) => {
+                tx.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn finish(&self, id: &str) {
+        self.jobs.lock().unwrap().remove(id);
+    }
+}
+
+// Sends progress events for one file of a job
+#[derive(Clone)]
+pub struct FileProgress {
+    app: AppHandle,
+    job_id: String,
+    input_path: String,
+}
+
+impl FileProgress {
+    pub fn new(app: &AppHandle, job_id: &str, input_path: &str) -> Self {
+        FileProgress { app: app.clone(), job_id: job_id.to_string(), input_path: input_path.to_string() }
+    }
+
+    pub fn report(&self, stage: JobStage, bytes_processed: u64, bytes_total: u64) {
+        self.send(stage, bytes_processed, bytes_total, None);
+    }
+
+    // Marks the file finished, with how it ended
+    pub fn finish(&self, status: FileStatus) {
+        self.send(JobStage::Done, 0, 0, Some(status));
+    }
+
+    fn send(&self, stage: JobStage, bytes_processed: u64, bytes_total: u64, status: Option<FileStatus>) {
+        let payload = JobProgress {
+            job_id: self.job_id.clone(),
+            input_path: self.input_path.clone(),
+            stage,
+            bytes_processed,
+            bytes_total,
+            percent: percent(stage, bytes_processed, bytes_total),
+            status,
+        };
+        if let Err(e) = self.app.emit(JOB_PROGRESS_EVENT, payload) {
+            warn!("Failed to emit {}: {}", JOB_PROGRESS_EVENT, e);
+        }
+    }
+}
+
+fn percent(stage: JobStage, bytes_processed: u64, bytes_total: u64) -> u8 {
+    let (start, end) = stage.range();
+    let done = (bytes_processed.min(bytes_total) * (end - start)).checked_div(bytes_total).unwrap_or(0);
+    (start + done) as u8
+}
+
+// Wraps the reader of a blocking encrypt or decrypt step, reporting progress as it is
+// read and failing the step once the job is cancelled
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: FileProgress,
+    cancel: CancelToken,
+    stage: JobStage,
+    bytes_read: u64,
+    bytes_total: u64,
+    last_percent: u8,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, progress: FileProgress, cancel: CancelToken, stage: JobStage, bytes_total: u64) -> Self {
+        progress.report(stage, 0, bytes_total);
+        let last_percent = percent(stage, 0, bytes_total);
+        ProgressReader { inner, progress, cancel, stage, bytes_read: 0, bytes_total, last_percent }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            // Not `Interrupted`, which readers retry
+            return Err(io::Error::other(CANCELLED));
+        }
+        let n = self.inner.read(buf)?;
+        self.bytes_read += n as u64;
+        // Only whole-percent changes are sent, so large files don't flood the frontend
+        let percent = percent(self.stage, self.bytes_read, self.bytes_total);
+        if percent != self.last_percent {
+            self.last_percent = percent;
+            self.progress.report(self.stage, self.bytes_read, self.bytes_total);
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,79 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use rusqlite::Connection;
+use tracing::info;
+use zeroize::Zeroizing;
+use crate::crypto;
+use crate::db;
+use crate::password;
+
+// Sensitive database fields (template mappings and recognizers) are encrypted with a
+// per-user data-encryption key (DEK). The DEK is stored only wrapped by a key derived
+// from the user's login password, is unwrapped on login, lives in the session while
+// signed in, and is zeroized when the session ends.
+
+pub type DataKey = Zeroizing<[u8; 32]>;
+
+const KDF_SALT_LEN: usize = 16;
+// Prefix marking an encrypted field; legacy rows hold plain JSON
+const ENCRYPTED_FIELD_PREFIX: &str = "enc1:";
+
+fn wrap_aad(email: &str) -> Vec<u8> {
+    format!("ciphershield-dek:{}", email).into_bytes()
+}
+
+// Generates a new random data-encryption key
+pub fn generate_data_key() -> Result<DataKey, String> {
+    crypto::generate_key()
+}
+
+// Wraps a data key under a password-derived key, returning the hex KDF salt and wrapped key
+pub fn wrap_data_key(data_key: &DataKey, password: &str, email: &str) -> Result<(String, String), String> {
+    let mut salt = [0u8; KDF_SALT_LEN];
+    SystemRandom::new().fill(&mut salt).map_err(|e| e.to_string())?;
+    let kek = password::derive_key(password, &salt)?;
+    let wrapped = crypto::seal(&kek, &wrap_aad(email), data_key.as_ref())?;
+    Ok((hex::encode(salt), hex::encode(wrapped)))
+}
+
+// Unwraps a stored data key with the user's password
+pub fn unwrap_data_key(salt: &str, wrapped: &str, password: &str, email: &str) -> Result<DataKey, String> {
+    let salt = hex::decode(salt).map_err(|e| e.to_string())?;
+    let wrapped = hex::decode(wrapped).map_err(|e| e.to_string())?;
+    let kek = password::derive_key(password, &salt)?;
+    let plain = crypto::open(&kek, &wrap_aad(email), &wrapped)?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    if plain.len() != key.len() {
+        return Err("Wrapped data key has the wrong length".to_string());
+    }
+    key.copy_from_slice(&plain);
+    Ok(key)
+}
+
+// Returns the user's data key, creating and storing one the first time an account without
+// a key signs in. The password must already have been verified.
+pub fn unlock_data_key(conn: &Connection, email: &str, password: &str) -> Result<DataKey, String> {
+    if let Some((salt, wrapped)) = db::get_user_key(conn, email).map_err(|e| e.to_string())? {
+        return unwrap_data_key(&salt, &wrapped, password, email);
+    }
+    let data_key = generate_data_key()?;
+    let (salt, wrapped) = wrap_data_key(&data_key, password, email)?;
+    db::save_user_key(conn, email, &salt, &wrapped).map_err(|e| e.to_string())?;
+    info!("Created data key for {}", email);
+    Ok(data_key)
+}
+
+// Encrypts a field value for storage; `context` names the table, owner and column
+pub fn encrypt_field(key: &DataKey, context: &str, value: &str) -> Result<String, String> {
+    let sealed = crypto::seal(key, context.as_bytes(), value.as_bytes())?;
+    Ok(format!("{}{}", ENCRYPTED_FIELD_PREFIX, hex::encode(sealed)))
+}
+
+// Decrypts a field stored by `encrypt_field`, passing legacy plaintext values through
+pub fn decrypt_field(key: &DataKey, context: &str, stored: &str) -> Result<String, String> {
+    let Some(encoded) = stored.strip_prefix(ENCRYPTED_FIELD_PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let sealed = hex::decode(encoded).map_err(|e| e.to_string())?;
+    let plain = crypto::open(key, context.as_bytes(), &sealed)?;
+    std::str::from_utf8(&plain).map(str::to_string).map_err(|e| e.to_string())
+}
@@ -2,52 +2,33 @@
 
 mod models;
 mod db;
+mod migrations;
+mod audit;
+mod audit_export;
 mod auth;
+mod crypto;
+mod engine;
+#[cfg(unix)]
+mod engine_socket;
+mod jobs;
+mod keystore;
+mod output;
+mod password;
+mod totp;
 mod processing;
+mod session;
+mod sidecar;
+mod sidecar_engine;
+mod tls;
 mod commands;
+mod workspace;
+mod wipe;
 
 use tauri::Builder;
-use tauri_plugin_shell::process::{CommandEvent, CommandChild};
-use tauri_plugin_shell::ShellExt;
 use tracing_subscriber;
-use std::sync::Mutex;
 use tauri::{Manager, WindowEvent};
-use std::process::{Command, Stdio};
-use std::io::Read;
-
-#[derive(Default)]
-struct SidecarState {
-    child: Mutex<Option<CommandChild>>,
-}
-
-#[cfg(target_family = "unix")]
-fn kill_tree(pid: u32) {
-    // Recursive kill children
-    if let Ok(mut child_cmd) = Command::new("pgrep")
-        .arg("-P")
-        .arg(pid.to_string())
-        .stdout(Stdio::piped())
-        .spawn() {
-        let mut output = String::new();
-        if let Some(mut stdout) = child_cmd.stdout.take() {
-            if stdout.read_to_string(&mut output).is_ok() {
-                for line in output.lines() {
-                    if let Ok(child_pid) = line.trim().parse::<u32>() {
-                        kill_tree(child_pid);
-                    }
-                }
-            }
-        }
-        let _ = child_cmd.wait();
-    }
-    // Kill the process
-    let _ = Command::new("kill").arg("-9").arg(pid.to_string()).spawn();
-}
-
-#[cfg(target_os = "windows")]
-fn kill_tree(pid: u32) {
-    let _ = Command::new("taskkill").arg("/PID").arg(pid.to_string()).arg("/T").arg("/F").spawn();
-}
+use std::path::PathBuf;
+use std::sync::Arc;
 
 // Initializes the Tauri application with plugins and command handlers
 fn main() {
@@ -58,38 +39,53 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_opener::init())
-        .manage(SidecarState::default())
-        .invoke_handler(tauri::generate_handler![auth::login, processing::process_files, processing::process_text, commands::get_templates])
+        .manage(sidecar::SidecarState::default())
+        .manage(session::SessionState::default())
+        .manage(jobs::JobRegistry::default())
+        .invoke_handler(tauri::generate_handler![
+            auth::login,
+            auth::logout,
+            auth::register,
+            auth::change_password,
+            auth::delete_account,
+            auth::verify_mfa,
+            auth::start_mfa_enrollment,
+            auth::confirm_mfa_enrollment,
+            auth::disable_mfa,
+            processing::process_files,
+            processing::process_text,
+            processing::cancel_job,
+            sidecar::engine_status,
+            engine::list_engines,
+            commands::get_templates,
+            commands::get_security_settings,
+            commands::update_security_settings,
+            commands::query_audit_log,
+            commands::export_audit_log,
+            commands::verify_audit_chain,
+            commands::list_history,
+            commands::rename_history_entry,
+            commands::delete_history_entry,
+            commands::reopen_output
+        ])
         .setup(|app| {
             let handle = app.handle().clone();  // Clone for use in event handlers
-            let sidecar_command = app.shell().sidecar("cipher-server").unwrap();
-            let (mut rx, child) = sidecar_command
-                .spawn()
-                .expect("failed to spawn sidecar");
-
-            let state: tauri::State<SidecarState> = app.state();
-            *state.child.lock().unwrap() = Some(child);
-
-            tauri::async_runtime::spawn(async move {
-                while let Some(event) = rx.recv().await {
-                    match event {
-                        CommandEvent::Stdout(line_bytes) => println!("Server stdout: {}", String::from_utf8_lossy(&line_bytes)),
-                        CommandEvent::Stderr(line_bytes) => println!("Server stderr: {}", String::from_utf8_lossy(&line_bytes)),
-                        _ => {}
-                    }
-                }
-            });
+            // Workspaces left by a crash hold plaintext intermediates
+            app.manage(workspace::Workspaces::init(&handle)?);
+            let data_dir = handle.path().app_local_data_dir().unwrap_or(PathBuf::from("."));
+            audit::load_device_key(&data_dir)?;
+            app.manage(db::Database::open(&handle)?);
+            session::spawn_idle_watcher(handle.clone());
+            sidecar::spawn_supervisor(handle.clone(), tls::load_or_create(&data_dir)?);
+            let mut engines = engine::EngineRegistry::default();
+            engines.register(Arc::new(sidecar_engine::SidecarEngine::new(handle.clone())));
+            app.manage(engines);
 
             let window = app.get_webview_window("main").expect("no main window");
             window.on_window_event(move |event| {
                 if let WindowEvent::CloseRequested { .. } = event {
-                    let state: tauri::State<SidecarState> = handle.state();
-                    let mut guard = state.child.lock().unwrap();
-                    if let Some(child) = guard.take() {
-                        let pid = child.pid();
-                        kill_tree(pid);
-                        let _ = child.kill();  // Fallback
-                    }
+                    sidecar::shutdown(&handle);
+                    handle.state::<workspace::Workspaces>().remove();
                     // No prevent_default() – let the app fully close
                 }
             });
@@ -0,0 +1,288 @@
+use rusqlite::{Connection, Result as SqlResult};
+use tracing::info;
+
+// Ordered schema migrations for secure.db. The applied version is stored in
+// `PRAGMA user_version`; each migration runs in its own transaction together
+// with the version bump, so a failed upgrade leaves the database untouched.
+//
+// Databases created before versioning report version 0 but may already contain
+// any of the tables from migrations 1-4, so those are idempotent. Later
+// migrations can assume the previous version exactly. Never edit a released
+// migration; append a new one with the next version number instead.
+
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&Connection) -> SqlResult<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "users, templates and processed_files", apply: initial_schema },
+    Migration { version: 2, description: "login attempts and settings", apply: login_attempts_and_settings },
+    Migration { version: 3, description: "multi-factor authentication", apply: mfa },
+    Migration { version: 4, description: "per-user template encryption keys", apply: template_owner_and_user_keys },
+    Migration { version: 5, description: "audit log", apply: audit_log },
+    Migration { version: 6, description: "hash-chained audit entries", apply: audit_chain },
+    Migration { version: 7, description: "processing history details", apply: processing_history },
+    Migration { version: 8, description: "template engines", apply: template_engine },
+    Migration { version: 9, description: "administrator role", apply: admin_role },
+];
+
+// Brings the database up to the latest schema version, returning the version it started at
+pub fn migrate(conn: &Connection) -> SqlResult<u32> {
+    migrate_with(conn, MIGRATIONS)
+}
+
+fn migrate_with(conn: &Connection, migrations: &[Migration]) -> SqlResult<u32> {
+    debug_assert!(
+        migrations.iter().enumerate().all(|(i, m)| m.version as usize == i + 1),
+        "migration versions must be contiguous and start at 1"
+    );
+    let current: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+    if current > latest {
+        let message = format!("Database schema version {} is newer than this app supports ({})", current, latest);
+        return Err(rusqlite::Error::ToSqlConversionFailure(message.into()));
+    }
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let tx = conn.unchecked_transaction()?;
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+        info!("Applied database migration {}: {}", migration.version, migration.description);
+    }
+    Ok(current)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> SqlResult<bool> {
+    conn.prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists([table, column])
+}
+
+fn initial_schema(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS users (
+            email TEXT PRIMARY KEY,
+            password_hash TEXT NOT NULL,
+            salt TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            mappings TEXT NOT NULL,
+            custom_recognizers TEXT NOT NULL DEFAULT '[]'
+        );
+        CREATE TABLE IF NOT EXISTS processed_files (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            path TEXT NOT NULL,
+            timestamp TEXT NOT NULL
+        );",
+    )
+}
+
+fn login_attempts_and_settings(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS login_attempts (
+            email TEXT PRIMARY KEY,
+            failed_count INTEGER NOT NULL DEFAULT 0,
+            locked_until INTEGER,
+            last_failed_at INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );",
+    )
+}
+
+fn mfa(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS mfa (
+            email TEXT PRIMARY KEY,
+            secret TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 0,
+            last_used_step INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS mfa_recovery_codes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            email TEXT NOT NULL,
+            code_hash TEXT NOT NULL,
+            used_at INTEGER
+        );",
+    )
+}
+
+fn template_owner_and_user_keys(conn: &Connection) -> SqlResult<()> {
+    if !has_column(conn, "templates", "owner")? {
+        conn.execute("ALTER TABLE templates ADD COLUMN owner TEXT", [])?;
+    }
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS user_keys (
+            email TEXT PRIMARY KEY,
+            kdf_salt TEXT NOT NULL,
+            wrapped_key TEXT NOT NULL
+        );",
+    )
+}
+
+fn audit_log(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            level TEXT NOT NULL,
+            operation TEXT NOT NULL,
+            user TEXT NOT NULL,
+            source TEXT NOT NULL,
+            status TEXT NOT NULL,
+            duration_ms INTEGER,
+            pii_count INTEGER NOT NULL DEFAULT 0,
+            details TEXT NOT NULL DEFAULT ''
+        );
+        CREATE INDEX audit_log_timestamp ON audit_log (timestamp);
+        CREATE INDEX audit_log_operation ON audit_log (operation);",
+    )
+}
+
+fn audit_chain(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE audit_log ADD COLUMN prev_hash TEXT;
+        ALTER TABLE audit_log ADD COLUMN entry_hash TEXT;
+        ALTER TABLE audit_log ADD COLUMN mac TEXT;",
+    )?;
+    // Entries written before chaining can only be sealed as they are now
+    crate::audit::seal_unchained_entries(conn)
+}
+
+fn processing_history(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE processed_files ADD COLUMN owner TEXT;
+        ALTER TABLE processed_files ADD COLUMN source_path TEXT NOT NULL DEFAULT '';
+        ALTER TABLE processed_files ADD COLUMN action TEXT NOT NULL DEFAULT '';
+        ALTER TABLE processed_files ADD COLUMN template_id INTEGER;
+        ALTER TABLE processed_files ADD COLUMN pii_count INTEGER NOT NULL DEFAULT 0;
+        CREATE INDEX processed_files_owner ON processed_files (owner);",
+    )
+}
+
+fn template_engine(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch("ALTER TABLE templates ADD COLUMN engine TEXT;")
+}
+
+// The account that installed the app, i.e. the oldest one, becomes the administrator
+fn admin_role(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE users ADD COLUMN is_admin INTEGER NOT NULL DEFAULT 0;
+        UPDATE users SET is_admin = 1 WHERE rowid = (SELECT MIN(rowid) FROM users);",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn latest_version() -> u32 {
+        MIGRATIONS.last().unwrap().version
+    }
+
+    fn user_version(conn: &Connection) -> u32 {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap()
+    }
+
+    fn table_exists(conn: &Connection, table: &str) -> bool {
+        conn.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")
+            .unwrap()
+            .exists([table])
+            .unwrap()
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(migrate(&conn).unwrap(), 0);
+        assert_eq!(user_version(&conn), latest_version());
+        assert!(table_exists(&conn, "audit_log"));
+        assert!(has_column(&conn, "users", "is_admin").unwrap());
+        // Running again is a no-op
+        assert_eq!(migrate(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn unversioned_legacy_database_is_upgraded_in_place() {
+        let conn = Connection::open_in_memory().unwrap();
+        // Databases from before versioning have the baseline tables but report version 0
+        for migration in &MIGRATIONS[..4] {
+            (migration.apply)(&conn).unwrap();
+        }
+        conn.execute("INSERT INTO users (email, password_hash, salt) VALUES ('first@example.com', 'hash', '')", []).unwrap();
+        conn.execute("INSERT INTO users (email, password_hash, salt) VALUES ('second@example.com', 'hash', '')", []).unwrap();
+        conn.execute("INSERT INTO templates (name, mappings) VALUES ('legacy', '[]')", []).unwrap();
+        assert_eq!(user_version(&conn), 0);
+
+        assert_eq!(migrate(&conn).unwrap(), 0);
+        assert_eq!(user_version(&conn), latest_version());
+        let templates: i64 = conn.query_row("SELECT COUNT(*) FROM templates WHERE owner IS NULL", [], |row| row.get(0)).unwrap();
+        assert_eq!(templates, 1);
+        let admins: Vec<String> = conn
+            .prepare("SELECT email FROM users WHERE is_admin = 1")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(admins, ["first@example.com"]);
+    }
+
+    #[test]
+    fn refuses_database_from_newer_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        let err = migrate(&conn).unwrap_err();
+        assert!(err.to_string().contains("newer than this app supports"));
+        assert_eq!(user_version(&conn), latest_version() + 1);
+        assert!(!table_exists(&conn, "users"));
+    }
+
+    fn broken_migration(conn: &Connection) -> SqlResult<()> {
+        conn.execute_batch(
+            "CREATE TABLE half_done (id INTEGER PRIMARY KEY);
+            INSERT INTO missing_table VALUES (1);",
+        )
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        let migrations = [
+            Migration { version: 1, description: "users, templates and processed_files", apply: initial_schema },
+            Migration { version: 2, description: "broken", apply: broken_migration },
+        ];
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(migrate_with(&conn, &migrations).is_err());
+        assert_eq!(user_version(&conn), 1);
+        assert!(table_exists(&conn, "users"));
+        assert!(!table_exists(&conn, "half_done"));
+    }
+
+    #[test]
+    fn audit_chain_migration_seals_existing_entries() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate_with(&conn, &MIGRATIONS[..5]).unwrap();
+        for (operation, user) in [("LOGIN", "a@example.com"), ("SETTINGS_UPDATED", "a@example.com"), ("LOGIN", "b@example.com")] {
+            conn.execute(
+                "INSERT INTO audit_log (timestamp, level, operation, user, source, status, pii_count, details)
+                 VALUES ('2024-01-01T00:00:00.000Z', 'INFO', ?1, ?2, 'desktop', 'SUCCESS', 0, '')",
+                params![operation, user],
+            )
+            .unwrap();
+        }
+
+        migrate(&conn).unwrap();
+        let report = crate::audit::verify_chain(&conn).unwrap();
+        assert!(report.valid, "{:?}", report.reason);
+        assert_eq!(report.total_entries, 3);
+        assert_eq!(report.verified_entries, 3);
+        assert!(report.head_hash.is_some());
+    }
+}
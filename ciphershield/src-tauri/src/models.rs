@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
 
 #[derive(Serialize, Deserialize)]
 pub struct User {
@@ -12,10 +13,177 @@ pub struct LoginInput {
     pub password: String,
 }
 
-#[derive(Serialize)]
+impl Drop for LoginInput {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+#[derive(Serialize, Default)]
 pub struct LoginOutput {
     pub success: bool,
     pub error: Option<String>,
+    pub token: Option<String>,
+    pub mfa_required: bool,
+    pub mfa_enrollment_required: bool,
+    pub mfa_challenge: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyMfaInput {
+    pub challenge: String,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmMfaInput {
+    pub challenge: Option<String>,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableMfaInput {
+    pub password: String,
+}
+
+impl Drop for DisableMfaInput {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+#[derive(Serialize)]
+pub struct MfaEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterInput {
+    pub email: String,
+    pub password: String,
+}
+
+impl Drop for RegisterInput {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordInput {
+    pub email: String,
+    pub current_password: String,
+    pub new_password: String,
+}
+
+impl Drop for ChangePasswordInput {
+    fn drop(&mut self) {
+        self.current_password.zeroize();
+        self.new_password.zeroize();
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountInput {
+    pub email: String,
+    pub password: String,
+}
+
+impl Drop for DeleteAccountInput {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+#[derive(Serialize)]
+pub struct AccountOutput {
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordPolicy {
+    Basic,
+    Standard,
+    Strong,
+    Enterprise,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SecuritySettings {
+    pub password_policy: PasswordPolicy,
+    pub session_timeout_minutes: u32,
+    pub max_login_attempts: u32,
+    pub require_mfa: bool,
+    // Overwrite intermediate files before deleting them
+    pub secure_delete: bool,
+    pub audit_forwarding: AuditForwarding,
+    pub engine_transport: EngineTransport,
+}
+
+impl Default for SecuritySettings {
+    fn default() -> Self {
+        SecuritySettings {
+            password_policy: PasswordPolicy::Strong,
+            session_timeout_minutes: 60,
+            max_login_attempts: 3,
+            require_mfa: false,
+            secure_delete: true,
+            audit_forwarding: AuditForwarding::default(),
+            engine_transport: EngineTransport::default(),
+        }
+    }
+}
+
+// How processing exchanges documents with the engine
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineTransport {
+    // Encrypted files in a job workspace, passed by path over HTTPS
+    Https,
+    // Content streamed over the engine's Unix socket without touching disk; Unix only,
+    // other platforms fall back to Https
+    Socket,
+}
+
+impl Default for EngineTransport {
+    fn default() -> Self {
+        if cfg!(unix) {
+            EngineTransport::Socket
+        } else {
+            EngineTransport::Https
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditExportFormat {
+    Csv,
+    Jsonl,
+    Cef,
+    Syslog,
+}
+
+// Live forwarding of new audit entries to a local syslog receiver
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AuditForwarding {
+    pub enabled: bool,
+    // Unix datagram socket path, or udp://host:port
+    pub target: String,
+    // Cef or Syslog; both are sent framed as RFC 5424 messages
+    pub format: AuditExportFormat,
+}
+
+impl Default for AuditForwarding {
+    fn default() -> Self {
+        AuditForwarding { enabled: false, target: "/dev/log".to_string(), format: AuditExportFormat::Syslog }
+    }
 }
 
 #[derive(Deserialize)]
@@ -25,6 +193,40 @@ pub struct FileInput {
     pub template_id: Option<i32>,
     pub save_template: bool,
     pub template_name: Option<String>,
+    #[serde(default)]
+    pub output: OutputPolicy,
+    // How many files to process at once; defaults to a small fixed number
+    #[serde(default)]
+    pub max_concurrency: Option<usize>,
+    // PII engine to use instead of the template's or the default
+    #[serde(default)]
+    pub engine: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    Overwrite,
+    Skip,
+    #[default]
+    Suffix,
+}
+
+// Where processed files are written and what they are called
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct OutputPolicy {
+    // Defaults to the directory of each input file
+    pub output_dir: Option<std::path::PathBuf>,
+    // Supports {stem}, {ext} and {action}
+    pub naming_pattern: String,
+    pub on_conflict: ConflictPolicy,
+}
+
+impl Default for OutputPolicy {
+    fn default() -> Self {
+        OutputPolicy { output_dir: None, naming_pattern: "{stem}.{action}.{ext}".to_string(), on_conflict: ConflictPolicy::Suffix }
+    }
 }
 
 #[derive(Deserialize)]
@@ -34,6 +236,15 @@ pub struct TextInput {
     pub save_template: bool,
     pub template_name: Option<String>,
     pub custom_recognizers: Vec<CustomRecognizer>,
+    // PII engine to use instead of the default
+    #[serde(default)]
+    pub engine: Option<String>,
+}
+
+impl Drop for TextInput {
+    fn drop(&mut self) {
+        self.text.zeroize();
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -43,16 +254,38 @@ pub struct CustomRecognizer {
     pub score: f64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct ProcessOutput {
     pub result: String,
     pub output_paths: Vec<String>,
+    // Inputs not processed because their output already existed
+    pub skipped_paths: Vec<String>,
     pub template_id: Option<i32>,
     pub error: Option<String>,
     pub items: Vec<MappingItem>,
+    // Outcome of each input file, in input order
+    pub files: Vec<FileResult>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Success,
+    Skipped,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FileResult {
+    pub input_path: String,
+    pub status: FileStatus,
+    pub output_path: Option<String>,
+    pub pii_count: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct MappingItem {
     pub original: String,
     pub anonymized: String,
@@ -60,18 +293,98 @@ pub struct MappingItem {
     pub confidence: f64,
 }
 
+// The original value is the PII itself, so wipe it when a job's results are dropped
+impl Drop for MappingItem {
+    fn drop(&mut self) {
+        self.original.zeroize();
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Template {
     pub id: i32,
     pub name: String,
     pub mappings: Vec<MappingItem>,
     pub custom_recognizers: Vec<CustomRecognizer>,
+    // PII engine the template was made with; None for templates saved before engines could be chosen
+    pub engine: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct ProcessedFile {
-    pub id: i32,
+    pub id: i64,
     pub name: String,
-    pub path: String,
+    pub source_path: String,
+    pub output_path: String,
+    pub action: String,
+    pub template_id: Option<i64>,
+    pub pii_count: i64,
+    pub timestamp: String,
+}
+
+#[derive(Deserialize)]
+pub struct RenameHistoryInput {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteHistoryInput {
+    pub id: i64,
+    // Also remove the output file from disk
+    #[serde(default)]
+    pub delete_output: bool,
+}
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
     pub timestamp: String,
-}
\ No newline at end of file
+    pub level: String,
+    pub operation: String,
+    pub user: String,
+    pub source: String,
+    pub status: String,
+    pub duration_ms: Option<i64>,
+    pub pii_count: i64,
+    pub details: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AuditQuery {
+    pub page: u32,
+    pub page_size: u32,
+    pub level: Option<String>,
+    pub operation: Option<String>,
+    pub status: Option<String>,
+    pub user: Option<String>,
+    pub search: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    pub total: i64,
+    pub page: u32,
+}
+
+#[derive(Deserialize)]
+pub struct AuditExportInput {
+    pub query: AuditQuery,
+    pub format: AuditExportFormat,
+    pub path: std::path::PathBuf,
+}
+
+#[derive(Serialize)]
+pub struct AuditChainReport {
+    pub generated_at: String,
+    pub valid: bool,
+    pub total_entries: usize,
+    pub verified_entries: usize,
+    pub mac_verified: bool,
+    pub first_broken_id: Option<i64>,
+    pub reason: Option<String>,
+    pub head_hash: Option<String>,
+}
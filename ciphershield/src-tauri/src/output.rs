@@ -0,0 +1,237 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use uuid::Uuid;
+use crate::models::{ConflictPolicy, OutputPolicy};
+use crate::wipe;
+
+// Maximum " (n)" suffix tried before giving up on finding a free name
+const MAX_SUFFIX: u32 = 999;
+
+fn action_label(action: &str) -> &str {
+    match action {
+        "anonymize" => "anonymized",
+        "deanonymize" => "deanonymized",
+        other => other,
+    }
+}
+
+// Checks a naming pattern before any file is processed
+pub fn validate_policy(policy: &OutputPolicy) -> Result<(), String> {
+    if !policy.naming_pattern.contains("{stem}") {
+        return Err("Output naming pattern must contain {stem}".to_string());
+    }
+    if policy.naming_pattern.contains(['/', '\\']) {
+        return Err("Output naming pattern cannot contain path separators".to_string());
+    }
+    if let Some(dir) = &policy.output_dir {
+        if !dir.is_dir() {
+            return Err(format!("Output directory {} does not exist", dir.display()));
+        }
+    }
+    Ok(())
+}
+
+// Builds the output path for an input file from the policy, before resolving conflicts.
+// `{ext}` is the extension of the result, as reported by the engine for the input's format,
+// so results open in the right applications even when the engine converts them.
+pub fn planned_path(input_path: &Path, ext: &str, policy: &OutputPolicy, action: &str) -> Result<PathBuf, String> {
+    let stem = input_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("Input {} has no file name", input_path.display()))?;
+    let pattern = if ext.is_empty() { policy.naming_pattern.replace(".{ext}", "") } else { policy.naming_pattern.clone() };
+    let name = pattern
+        .replace("{stem}", &stem)
+        .replace("{ext}", ext)
+        .replace("{action}", action_label(action));
+    let name = name.trim_end_matches('.');
+    if name.is_empty() || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!("Output naming pattern produced an invalid file name for {}", input_path.display()));
+    }
+    let dir = match &policy.output_dir {
+        Some(dir) => dir.clone(),
+        None => input_path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    let path = dir.join(name);
+    if same_file(input_path, &dir, name) {
+        return Err(format!("Output would overwrite the input file {}", input_path.display()));
+    }
+    Ok(path)
+}
+
+// Whether `dir`/`name` is the input file, however either path is spelled
+fn same_file(input_path: &Path, dir: &Path, name: &str) -> bool {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    match (input_path.canonicalize(), dir.canonicalize()) {
+        (Ok(input), Ok(dir)) => dir.join(name) == input,
+        _ => dir.join(name) == input_path,
+    }
+}
+
+fn suffixed(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+// create_new fails atomically if the name was taken since it was planned
+fn open_target(target: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(target)
+}
+
+// Where a result is written. An overwrite goes to a staging file beside the destination,
+// so the existing file stays intact until the result is complete.
+struct Target {
+    path: PathBuf,
+    file: File,
+    staging: Option<PathBuf>,
+}
+
+impl Target {
+    fn written_path(&self) -> &Path {
+        self.staging.as_deref().unwrap_or(&self.path)
+    }
+}
+
+// Creates the file a result is written into under the policy, so the result can be
+// decrypted straight into it. Returns None if the destination exists and the policy is to skip.
+fn create_target(planned: &Path, policy: &OutputPolicy) -> Result<Option<Target>, String> {
+    let failed = |path: &Path, e: io::Error| format!("Failed to create {}: {}", path.display(), e);
+    match policy.on_conflict {
+        ConflictPolicy::Overwrite => {
+            let name = planned.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let staging = planned.with_file_name(format!(".{}.{}.partial", name, Uuid::new_v4()));
+            let file = open_target(&staging).map_err(|e| failed(&staging, e))?;
+            Ok(Some(Target { path: planned.to_path_buf(), file, staging: Some(staging) }))
+        }
+        ConflictPolicy::Skip => match open_target(planned) {
+            Ok(file) => Ok(Some(Target { path: planned.to_path_buf(), file, staging: None })),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+            Err(e) => Err(failed(planned, e)),
+        },
+        ConflictPolicy::Suffix => {
+            for n in 0..=MAX_SUFFIX {
+                let target = if n == 0 { planned.to_path_buf() } else { suffixed(planned, n) };
+                match open_target(&target) {
+                    Ok(file) => return Ok(Some(Target { path: target, file, staging: None })),
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(failed(&target, e)),
+                }
+            }
+            Err(format!("No free output name found for {}", planned.display()))
+        }
+    }
+}
+
+// Streams a result straight into its destination through `fill`, so the only plaintext
+// written is the output itself. Returns None if the policy skipped an existing file.
+pub fn write_output<F>(planned: &Path, policy: &OutputPolicy, secure_delete: bool, fill: F) -> Result<Option<PathBuf>, String>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<(), String>,
+{
+    let Some(target) = create_target(planned, policy)? else {
+        return Ok(None);
+    };
+    let mut out = BufWriter::new(&target.file);
+    let written = fill(&mut out)
+        .and_then(|_| out.flush().map_err(|e| e.to_string()))
+        .and_then(|_| target.file.sync_all().map_err(|e| e.to_string()))
+        .and_then(|_| match &target.staging {
+            Some(staging) => std::fs::rename(staging, &target.path)
+                .map_err(|e| format!("Failed to replace {}: {}", target.path.display(), e)),
+            None => Ok(()),
+        });
+    drop(out);
+    if let Err(e) = written {
+        let partial = target.written_path().to_path_buf();
+        drop(target.file);
+        // A partial result is still plaintext. Only the file this job created is removed,
+        // never one it was going to replace.
+        if let Err(wipe_err) = wipe::remove_file(&partial, secure_delete) {
+            warn!("Failed to remove partial output {:?}: {}", partial, wipe_err);
+        }
+        return Err(e);
+    }
+    Ok(Some(target.path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_input_extension_when_the_format_is_unchanged() {
+        let planned = planned_path(Path::new("/docs/data.csv"), "csv", &OutputPolicy::default(), "anonymize").unwrap();
+        assert_eq!(planned, Path::new("/docs/data.anonymized.csv"));
+    }
+
+    #[test]
+    fn names_pdf_results_after_the_text_the_engine_returns() {
+        let planned = planned_path(Path::new("/docs/report.pdf"), "txt", &OutputPolicy::default(), "anonymize").unwrap();
+        assert_eq!(planned, Path::new("/docs/report.anonymized.txt"));
+    }
+
+    #[test]
+    fn drops_the_extension_placeholder_for_files_without_one() {
+        let planned = planned_path(Path::new("/docs/README"), "", &OutputPolicy::default(), "deanonymize").unwrap();
+        assert_eq!(planned, Path::new("/docs/README.deanonymized"));
+    }
+
+    #[test]
+    fn refuses_to_overwrite_the_input() {
+        let policy = OutputPolicy { naming_pattern: "{stem}.{ext}".to_string(), ..OutputPolicy::default() };
+        assert!(planned_path(Path::new("/docs/notes.txt"), "txt", &policy, "anonymize").is_err());
+        let planned = planned_path(Path::new("/docs/notes.pdf"), "txt", &policy, "anonymize").unwrap();
+        assert_eq!(planned, Path::new("/docs/notes.txt"));
+    }
+
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cs-output-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn refuses_to_overwrite_the_input_through_another_spelling() {
+        let dir = scratch_dir();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.csv"), "x").unwrap();
+        let policy = OutputPolicy { output_dir: Some(dir.join("sub").join("..")), naming_pattern: "{stem}.{ext}".to_string(), ..OutputPolicy::default() };
+        assert!(planned_path(&dir.join("a.csv"), "csv", &policy, "anonymize").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_overwrite_keeps_the_existing_file() {
+        let dir = scratch_dir();
+        let existing = dir.join("report.txt");
+        std::fs::write(&existing, "earlier result").unwrap();
+        let policy = OutputPolicy { on_conflict: ConflictPolicy::Overwrite, ..OutputPolicy::default() };
+        let result = write_output(&existing, &policy, false, |out| {
+            out.write_all(b"half a").map_err(|e| e.to_string())?;
+            Err("Cancelled".to_string())
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "earlier result");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1, "staging file left behind");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn overwrite_replaces_the_existing_file_once_complete() {
+        let dir = scratch_dir();
+        let existing = dir.join("report.txt");
+        std::fs::write(&existing, "earlier result").unwrap();
+        let policy = OutputPolicy { on_conflict: ConflictPolicy::Overwrite, ..OutputPolicy::default() };
+        let written = write_output(&existing, &policy, false, |out| out.write_all(b"new result").map_err(|e| e.to_string())).unwrap();
+        assert_eq!(written.as_deref(), Some(existing.as_path()));
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "new result");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use std::num::NonZeroU32;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use zeroize::Zeroizing;
+
+// Password hashes are stored as PHC strings (`$argon2id$v=19$m=..,t=..,p=..$salt$hash`)
+// so the algorithm and cost travel with each record. Records created before PHC
+// support hold a hex PBKDF2-HMAC-SHA256 hash with the salt in a separate column;
+// they still verify and are upgraded by `needs_rehash` on the next login.
+
+// Argon2id cost used for new hashes; raising these upgrades records on their next login
+const ARGON2_M_COST_KIB: u32 = 19 * 1024;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+const SALT_LEN: usize = 16;
+const LEGACY_PBKDF2_ITERATIONS: u32 = 100000;
+
+fn argon2() -> Result<Argon2<'static>, String> {
+    let params = Params::new(ARGON2_M_COST_KIB, ARGON2_T_COST, ARGON2_P_COST, None).map_err(|e| e.to_string())?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+// Hashes a password with Argon2id and a fresh salt, returning a PHC string
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new().fill(&mut salt).map_err(|e| e.to_string())?;
+    let salt = SaltString::encode_b64(&salt).map_err(|e| e.to_string())?;
+    let hash = argon2()?.hash_password(password.as_bytes(), &salt).map_err(|e| e.to_string())?;
+    Ok(hash.to_string())
+}
+
+// Derives a 32-byte key-encryption key from a password with the same Argon2id cost as
+// password hashes. The salt must be distinct from the one in the password hash.
+pub fn derive_key(password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, String> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    argon2()?.hash_password_into(password.as_bytes(), salt, key.as_mut()).map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+// Verifies a password against a stored PHC string, or a legacy hex PBKDF2 hash and salt
+pub fn verify_password(password: &str, stored_hash: &str, legacy_salt: &str) -> Result<bool, String> {
+    if stored_hash.starts_with('$') {
+        let parsed = PasswordHash::new(stored_hash).map_err(|e| e.to_string())?;
+        if parsed.algorithm != argon2::ARGON2ID_IDENT {
+            return Err(format!("Unsupported password hash algorithm {}", parsed.algorithm));
+        }
+        // Verification uses the parameters recorded in the hash, not the current defaults
+        return Ok(Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok());
+    }
+    let salt = hex::decode(legacy_salt).map_err(|e| e.to_string())?;
+    let expected = hex::decode(stored_hash).map_err(|e| e.to_string())?;
+    Ok(pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(LEGACY_PBKDF2_ITERATIONS).unwrap(),
+        &salt,
+        password.as_bytes(),
+        &expected,
+    )
+    .is_ok())
+}
+
+// Returns true if a stored hash uses a legacy algorithm or weaker parameters than the current ones
+pub fn needs_rehash(stored_hash: &str) -> bool {
+    let Ok(parsed) = PasswordHash::new(stored_hash) else {
+        return true;
+    };
+    if parsed.algorithm != argon2::ARGON2ID_IDENT {
+        return true;
+    }
+    match Params::try_from(&parsed) {
+        Ok(params) => {
+            params.m_cost() < ARGON2_M_COST_KIB || params.t_cost() < ARGON2_T_COST || params.p_cost() < ARGON2_P_COST
+        }
+        Err(_) => true,
+    }
+}
+
+// Spends the same effort as a real verification so unknown accounts can't be told apart by timing
+pub fn dummy_verify(password: &str) {
+    let _ = hash_password(password);
+}
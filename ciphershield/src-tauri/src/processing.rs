@@ -1,212 +1,505 @@
-use std::fs::{File, create_dir_all};
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use ring::aead::{LessSafeKey, Nonce, NONCE_LEN, UnboundKey, AES_256_GCM, Aad, Tag};
-use ring::rand::{SecureRandom, SystemRandom};
-use tauri::{command, AppHandle, Manager};
-use tracing::info;
-use tokio::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tauri::{command, AppHandle, Emitter, Manager, State};
+use tracing::{info, warn};
 use chrono::Utc;
-use hex;
-use rusqlite::params;
 use serde_json;
-use crate::db::{get_secure_db, insert_template};
-use crate::models::{FileInput, TextInput, ProcessOutput, MappingItem};
-use reqwest::Client;
-
-async fn get_client() -> Result<Client, String> {
-    reqwest::Client::builder()
-        .danger_accept_invalid_certs(true)
-        .build()
-        .map_err(|e| e.to_string())
+use tokio::sync::Semaphore;
+use zeroize::Zeroizing;
+use crate::audit::{self, AuditEvent};
+use crate::engine::{self, Document, EngineRegistry, EngineRequest, PiiEngine, Processed};
+use crate::jobs::{CancelToken, FileProgress, JobFinished, JobRegistry, JOB_FINISHED_EVENT};
+use crate::keystore::DataKey;
+use crate::db::{get_security_settings, get_template_engine, get_template_mappings, insert_processed_file, insert_template, Database};
+use crate::session::SessionState;
+use crate::models::{FileInput, TextInput, ProcessOutput, MappingItem, ProcessedFile, OutputPolicy, ConflictPolicy, CustomRecognizer, FileResult, FileStatus, SecuritySettings};
+use crate::output;
+use crate::wipe;
+
+// Audit source recorded for text typed or pasted into the app
+const TEXT_SOURCE: &str = "direct_input";
+// Files processed at once when the request doesn't say
+const DEFAULT_CONCURRENCY: usize = 4;
+const MAX_CONCURRENCY: usize = 16;
+// Times a file is anonymized before giving up on agreeing with the rest of its batch
+const MAX_ANONYMIZE_ATTEMPTS: usize = 3;
+
+async fn load_settings(database: &Database) -> Result<SecuritySettings, String> {
+    database.run(|conn| get_security_settings(conn).map_err(|e| e.to_string())).await
 }
 
-fn encrypt_file(input_path: &PathBuf, output_path: &PathBuf, key_bytes: &[u8; 32]) -> Result<(), String> {
-    println!("Encrypting file: {:?} to {:?}", input_path, output_path);
-    let rng = SystemRandom::new();
-    let mut nonce_bytes = [0u8; NONCE_LEN];
-    rng.fill(&mut nonce_bytes).map_err(|e| e.to_string())?;
-    let nonce = Nonce::assume_unique_for_key(nonce_bytes.try_into().map_err(|_| "Nonce error".to_string())?);
-    let key = LessSafeKey::new(
-        UnboundKey::new(&AES_256_GCM, key_bytes).map_err(|e| e.to_string())?,
-    );
-
-    let mut file_in = File::open(input_path).map_err(|e| e.to_string())?;
-    let mut contents = Vec::new();
-    file_in.read_to_end(&mut contents).map_err(|e| e.to_string())?;
-    let tag = key.seal_in_place_separate_tag(nonce, Aad::empty(), &mut contents).map_err(|e| e.to_string())?;
-    let mut file_out = File::create(output_path).map_err(|e| e.to_string())?;
-    file_out.write_all(&nonce_bytes).map_err(|e| e.to_string())?;
-    file_out.write_all(&contents).map_err(|e| e.to_string())?;
-    file_out.write_all(tag.as_ref()).map_err(|e| e.to_string())?;
-    println!("File encrypted successfully");
-    Ok(())
+// Sends one file through the engine and writes the result at `planned` under the output policy,
+// returning the final output path (None if it was skipped) and the PII items the engine reported
+async fn process_file(
+    batch: &Batch,
+    input_path: PathBuf,
+    mappings: &[MappingItem],
+    planned: &Path,
+    policy: &OutputPolicy,
+    progress: &FileProgress,
+) -> Result<Option<(String, Vec<MappingItem>)>, String> {
+    batch.cancel.check()?;
+    let request = EngineRequest {
+        document: Document::File { input_path, planned, policy, progress },
+        mappings,
+        custom_recognizers: &[],
+        settings: &batch.settings,
+        cancel: &batch.cancel,
+    };
+    let output = engine::run(batch.engine.as_ref(), &batch.action, request).await?;
+    match output.processed {
+        Processed::File(path) => Ok(path.map(|path| (path.to_string_lossy().into_owned(), output.items))),
+        Processed::Text(_) => Err("Engine returned text for a file".to_string()),
+    }
 }
 
-fn decrypt_file(input_path: &PathBuf, output_path: &PathBuf, key_bytes: &[u8; 32]) -> Result<(), String> {
-    println!("Decrypting file: {:?} to {:?}", input_path, output_path);
-    let mut file_in = File::open(input_path).map_err(|e| e.to_string())?;
-    let mut contents = Vec::new();
-    file_in.read_to_end(&mut contents).map_err(|e| e.to_string())?;
-    if contents.len() < NONCE_LEN + AES_256_GCM.tag_len() {
-        return Err("Invalid file format".to_string());
-    }
-    let nonce_bytes = &contents[0..NONCE_LEN];
-    let cipher_len = contents.len() - NONCE_LEN - AES_256_GCM.tag_len();
-    let mut cipher_text = contents[NONCE_LEN..NONCE_LEN + cipher_len].to_vec();
-    let tag_bytes = &contents[NONCE_LEN + cipher_len..];
-    let tag: Tag = tag_bytes.try_into().map_err(|_| "Invalid tag".to_string())?;
-    let nonce = Nonce::assume_unique_for_key(nonce_bytes.try_into().map_err(|_| "Invalid nonce".to_string())?);
-    let key = LessSafeKey::new(
-        UnboundKey::new(&AES_256_GCM, key_bytes).map_err(|e| e.to_string())?,
-    );
-    key.open_in_place_separate_tag(nonce, Aad::empty(), tag, &mut cipher_text, 0..).map_err(|e| e.to_string())?;
-    let mut file_out = File::create(output_path).map_err(|e| e.to_string())?;
-    file_out.write_all(&cipher_text).map_err(|e| e.to_string())?;
-    println!("File decrypted successfully");
-    Ok(())
+// Settings shared by every file in a batch
+struct Batch {
+    app: AppHandle,
+    database: Database,
+    user: String,
+    action: String,
+    template_id: Option<i64>,
+    policy: OutputPolicy,
+    settings: SecuritySettings,
+    engine: Arc<dyn PiiEngine>,
+    job_id: String,
+    cancel: CancelToken,
+    mappings: BatchMappings,
 }
 
+// The replacement each original value gets across a batch. Grows as files finish, so later
+// files reuse the replacements chosen for earlier ones; the first file to finish with a value
+// fixes its replacement for the rest of the batch.
+struct BatchMappings(Mutex<Vec<MappingItem>>);
+
+impl BatchMappings {
+    fn new(mappings: Vec<MappingItem>) -> Self {
+        BatchMappings(Mutex::new(mappings))
+    }
+
+    // Adds the items whose originals aren't mapped yet and returns how many were rejected
+    // because their original already has a different replacement, or their replacement
+    // already stands for a different original
+    fn merge(&self, items: &[MappingItem]) -> usize {
+        let mut mappings = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let mut rejected = 0;
+        for item in items {
+            let same_original = mappings.iter().find(|m| m.original == item.original);
+            let same_replacement = mappings.iter().find(|m| m.anonymized == item.anonymized);
+            match (same_original, same_replacement) {
+                (None, None) => mappings.push(item.clone()),
+                (Some(existing), _) if existing.anonymized == item.anonymized => {}
+                _ => rejected += 1,
+            }
+        }
+        rejected
+    }
+
+    fn snapshot(&self) -> Vec<MappingItem> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+// Anonymizes a file so its replacements agree with the rest of the batch. The engine reuses
+// the replacements it is given and numbers new ones, so files processed at the same time can
+// give one value different replacements or two values the same one; a file that loses that
+// race is redone over its own output with the replacements that won.
+// `attempt` runs the engine with the given mappings, writing over the given output on a redo.
+async fn anonymize_consistently<F, Fut>(
+    mappings: &BatchMappings,
+    secure_delete: bool,
+    mut attempt: F,
+) -> Result<Option<(String, Vec<MappingItem>)>, String>
+where
+    F: FnMut(Vec<MappingItem>, Option<PathBuf>) -> Fut,
+    Fut: Future<Output = Result<Option<(String, Vec<MappingItem>)>, String>>,
+{
+    let mut redo = None;
+    for _ in 0..MAX_ANONYMIZE_ATTEMPTS {
+        let Some((output_path, items)) = attempt(mappings.snapshot(), redo.take()).await? else {
+            return Ok(None);
+        };
+        let rejected = mappings.merge(&items);
+        if rejected == 0 {
+            return Ok(Some((output_path, items)));
+        }
+        info!("Redoing {}: {} values were given other replacements by files processed alongside it", output_path, rejected);
+        redo = Some(PathBuf::from(output_path));
+    }
+    // Replacements that disagree with the saved template couldn't be reversed
+    if let Some(output_path) = redo {
+        if let Err(e) = wipe::remove_file(&output_path, secure_delete) {
+            warn!("Failed to remove inconsistent output {:?}: {}", output_path, e);
+        }
+    }
+    Err("Replacements kept changing while processing this file".to_string())
+}
 
-// Processes multiple files with anonymization or deanonymization
+fn file_result(input_path: &str, status: FileStatus) -> FileResult {
+    FileResult { input_path: input_path.to_string(), status, output_path: None, pii_count: 0, error: None }
+}
+
+// Processes one file of a batch and reports how it ended
+async fn process_batch_file(batch: Arc<Batch>, input_path: PathBuf) -> FileResult {
+    let source_path = input_path.to_string_lossy().into_owned();
+    let progress = FileProgress::new(&batch.app, &batch.job_id, &source_path);
+    let result = record_batch_file(&batch, input_path, &progress).await;
+    progress.finish(result.status);
+    result
+}
+
+// Processes one file of a batch, recording it in the audit log and history
+async fn record_batch_file(batch: &Batch, input_path: PathBuf, progress: &FileProgress) -> FileResult {
+    let started = Instant::now();
+    let source = input_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let source_path = input_path.to_string_lossy().into_owned();
+    if batch.cancel.is_cancelled() {
+        return file_result(&source_path, FileStatus::Cancelled);
+    }
+    let result = async {
+        let ext = input_path.extension().and_then(|os| os.to_str()).unwrap_or("");
+        let planned = output::planned_path(&input_path, &batch.engine.output_format(ext), &batch.policy, &batch.action)?;
+        if batch.policy.on_conflict == ConflictPolicy::Skip && planned.exists() {
+            info!("Skipping {:?}: {:?} already exists", input_path, planned);
+            return Ok(None);
+        }
+        if batch.action != "anonymize" {
+            return process_file(batch, input_path, &batch.mappings.snapshot(), &planned, &batch.policy, progress).await;
+        }
+        let overwrite = OutputPolicy { on_conflict: ConflictPolicy::Overwrite, ..batch.policy.clone() };
+        let (planned, overwrite) = (&planned, &overwrite);
+        anonymize_consistently(&batch.mappings, batch.settings.secure_delete, |mappings, redo| {
+            let input_path = input_path.clone();
+            async move {
+                match redo {
+                    Some(output_path) => process_file(batch, input_path, &mappings, &output_path, overwrite, progress).await,
+                    None => process_file(batch, input_path, &mappings, planned, &batch.policy, progress).await,
+                }
+            }
+        })
+        .await
+    }
+    .await;
+    match result {
+        Ok(None) => file_result(&source_path, FileStatus::Skipped),
+        Ok(Some((output_path, items))) => {
+            let pii_count = items.len();
+            let event = AuditEvent::success(audit::FILE_PROCESSED, &batch.user)
+                .source(&source)
+                .pii_count(pii_count)
+                .duration(started)
+                .details(&format!("File {} with {} PII elements", batch.action, pii_count));
+            let record = ProcessedFile {
+                id: 0,
+                name: source,
+                source_path: source_path.clone(),
+                output_path: output_path.clone(),
+                action: batch.action.clone(),
+                template_id: batch.template_id,
+                pii_count: pii_count as i64,
+                timestamp: audit::format_timestamp(Utc::now()),
+            };
+            let owner = batch.user.clone();
+            let recorded = batch
+                .database
+                .run(move |conn| {
+                    audit::record(conn, event);
+                    insert_processed_file(conn, &owner, &record).map_err(|e| e.to_string())
+                })
+                .await;
+            if let Err(e) = recorded {
+                warn!("Failed to record history for {}: {}", source_path, e);
+            }
+            FileResult { output_path: Some(output_path), pii_count, ..file_result(&source_path, FileStatus::Success) }
+        }
+        Err(_) if batch.cancel.is_cancelled() => file_result(&source_path, FileStatus::Cancelled),
+        Err(e) => {
+            let event = AuditEvent::failure(audit::PROCESSING_FAILED, audit::ERROR, &batch.user, &e).source(&source).duration(started);
+            audit::record_async(&batch.database, event).await;
+            FileResult { error: Some(e), ..file_result(&source_path, FileStatus::Failed) }
+        }
+    }
+}
+
+// Starts processing multiple files with anonymization or deanonymization, several at a time,
+// and returns the job id. Progress and the final `ProcessOutput` arrive as job events; a
+// failed file is reported in its result rather than failing the batch.
 #[command]
-pub async fn process_files(app: AppHandle, input: FileInput) -> Result<ProcessOutput, String> {
+pub async fn process_files(
+    app: AppHandle,
+    database: State<'_, Database>,
+    session: State<'_, SessionState>,
+    token: String,
+    jobs: State<'_, JobRegistry>,
+    engines: State<'_, EngineRegistry>,
+    input: FileInput,
+) -> Result<String, String> {
+    let (user, data_key) = session.require_data_key(&token)?;
     info!("Processing files: {:?}", input.files);
-    let db = get_secure_db(&app).map_err(|e| e.to_string())?;
-    let mut output_paths = Vec::new();
-    let mut mappings = if let Some(id) = input.template_id {
-        let mappings_json: String = db
-            .query_row(
-                "SELECT mappings FROM templates WHERE id = ?1",
-                params![id],
-                |row| row.get(0),
-            )
-            .map_err(|e| e.to_string())?;
-        serde_json::from_str(&mappings_json).map_err(|e| e.to_string())?
+    output::validate_policy(&input.output)?;
+    let (mappings, template_engine) = if let Some(id) = input.template_id {
+        let (user, data_key) = (user.clone(), data_key.clone());
+        database
+            .run(move |conn| {
+                let mappings = get_template_mappings(conn, id, &user, &data_key)
+                    .map_err(|e| e.to_string())?
+                    .ok_or_else(|| "Template not found".to_string())?;
+                let engine = get_template_engine(conn, id, &user).map_err(|e| e.to_string())?;
+                Ok((mappings, engine))
+            })
+            .await?
     } else {
-        Vec::new()
+        (Vec::new(), None)
     };
-    let client = get_client().await?;
-    for input_path in input.files {
-        let rng = SystemRandom::new();
-        let mut key = [0u8; 32];
-        rng.fill(&mut key).map_err(|e| e.to_string())?;
-        let temp_dir = app.path().app_local_data_dir().map_err(|e| e.to_string())?.join("temp");
-        create_dir_all(&temp_dir).map_err(|e| e.to_string())?;
-        let encrypted_path = temp_dir.join("temp_input.enc");
-        encrypt_file(&input_path, &encrypted_path, &key)?;
-        let output_path = temp_dir.join("temp_output.enc");
-        let original_ext = input_path.extension().and_then(|os| os.to_str()).unwrap_or("").to_string();
-        let body = serde_json::json!({
-            "action": input.action,
-            "input_path": encrypted_path.to_string_lossy().into_owned(),
-            "output_path": output_path.to_string_lossy().into_owned(),
-            "password": hex::encode(key),
-            "mappings": mappings,
-            "chunk_size": 1048576,
-            "original_ext": original_ext,
-            "custom_recognizers": vec![] as Vec<serde_json::Value>
-        });
-        let response = client
-            .post("https://127.0.0.1:8000/process_file")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| e.to_string())?;
-        if response.status().is_success() {
-            let json: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-            let dec_path = temp_dir.join("dec.out");
-            decrypt_file(&output_path, &dec_path, &key)?;
-            output_paths.push(dec_path.to_string_lossy().to_string());
-            if input.action == "anonymize" {
-                if let Some(new_items) = json["items"].as_array() {
-                    let new_mappings: Vec<MappingItem> = new_items
-                        .iter()
-                        .cloned()
-                        .map(serde_json::from_value)
-                        .collect::<Result<_, _>>()
-                        .map_err(|e| e.to_string())?;
-                    mappings.extend(new_mappings);
-                }
-            }
-        } else {
-            let err_text = response.text().await.map_err(|e| e.to_string())?;
-            return Err(err_text);
+    let engine = engines.select(input.engine.as_deref(), template_engine.as_deref())?;
+    let settings = load_settings(&database).await?;
+    let (job_id, cancel) = jobs.start();
+    let batch = Arc::new(Batch {
+        app,
+        database: database.inner().clone(),
+        user,
+        action: input.action.clone(),
+        template_id: input.template_id.map(i64::from),
+        policy: input.output.clone(),
+        settings,
+        engine,
+        job_id: job_id.clone(),
+        cancel,
+        mappings: BatchMappings::new(mappings),
+    });
+    tauri::async_runtime::spawn(async move {
+        let result = run_batch(&batch, input, data_key).await;
+        batch.app.state::<JobRegistry>().finish(&batch.job_id);
+        let finished = JobFinished {
+            job_id: batch.job_id.clone(),
+            cancelled: batch.cancel.is_cancelled(),
+            error: result.as_ref().err().cloned(),
+            output: result.ok(),
+        };
+        if let Err(e) = batch.app.emit(JOB_FINISHED_EVENT, finished) {
+            warn!("Failed to emit {}: {}", JOB_FINISHED_EVENT, e);
         }
+    });
+    Ok(job_id)
+}
+
+// Cancels a running file processing job
+#[command]
+pub async fn cancel_job(session: State<'_, SessionState>, token: String, jobs: State<'_, JobRegistry>, job_id: String) -> Result<(), String> {
+    session.require(&token)?;
+    if !jobs.cancel(&job_id) {
+        return Err("Job not found or already finished".to_string());
+    }
+    info!("Cancelling job {}", job_id);
+    Ok(())
+}
+
+async fn run_batch(batch: &Arc<Batch>, input: FileInput, data_key: DataKey) -> Result<ProcessOutput, String> {
+    let concurrency = input.max_concurrency.unwrap_or(DEFAULT_CONCURRENCY).clamp(1, MAX_CONCURRENCY);
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let tasks: Vec<_> = input
+        .files
+        .iter()
+        .map(|input_path| {
+            let (batch, permits, input_path) = (batch.clone(), permits.clone(), input_path.clone());
+            tauri::async_runtime::spawn(async move {
+                let _permit = permits.acquire_owned().await.map_err(|e| e.to_string())?;
+                Ok::<_, String>(process_batch_file(batch, input_path).await)
+            })
+        })
+        .collect();
+    let mut files = Vec::with_capacity(tasks.len());
+    for (task, input_path) in tasks.into_iter().zip(&input.files) {
+        let result = task.await.map_err(|e| e.to_string()).and_then(|result| result);
+        files.push(result.unwrap_or_else(|e| FileResult {
+            error: Some(e),
+            ..file_result(&input_path.to_string_lossy(), FileStatus::Failed)
+        }));
     }
+    let count = |status: FileStatus| files.iter().filter(|f| f.status == status).count();
+    let (failed, cancelled) = (count(FileStatus::Failed), count(FileStatus::Cancelled));
+    if cancelled > 0 {
+        let details = format!("Job cancelled with {} of {} files unprocessed", cancelled, files.len());
+        audit::record_async(&batch.database, AuditEvent::failure(audit::PROCESSING_CANCELLED, audit::WARNING, &batch.user, &details)).await;
+    }
+    let mappings = batch.mappings.snapshot();
+    // Saved even after a cancellation, since files that finished need it to be reversed
     let template_id = if input.save_template && input.action == "anonymize" {
         let name = input.template_name.unwrap_or(format!("template_{}", Utc::now().timestamp()));
-        let mappings_json = serde_json::to_string(&mappings).map_err(|e| e.to_string())?;
-        let custom_recognizers_json = serde_json::to_string(&Vec::<crate::models::CustomRecognizer>::new()).map_err(|e| e.to_string())?;
-        Some(insert_template(&db, &name, &mappings_json, &custom_recognizers_json).map_err(|e| e.to_string())?)
+        let mappings_json = Zeroizing::new(serde_json::to_string(&mappings).map_err(|e| e.to_string())?);
+        let custom_recognizers_json = serde_json::to_string(&Vec::<CustomRecognizer>::new()).map_err(|e| e.to_string())?;
+        let (user, engine) = (batch.user.clone(), batch.engine.capabilities().id);
+        Some(
+            batch
+                .database
+                .run(move |conn| insert_template(conn, &user, &data_key, &name, &mappings_json, &custom_recognizers_json, &engine).map_err(|e| e.to_string()))
+                .await?,
+        )
     } else {
         None
     };
+    let paths_with = |status: FileStatus| -> Vec<String> {
+        files.iter().filter(|f| f.status == status).map(|f| f.output_path.clone().unwrap_or_else(|| f.input_path.clone())).collect()
+    };
+    let (output_paths, skipped_paths) = (paths_with(FileStatus::Success), paths_with(FileStatus::Skipped));
+    let (result, error) = if cancelled > 0 {
+        ("Cancelled".to_string(), Some(format!("Cancelled with {} of {} files unprocessed", cancelled, files.len())))
+    } else {
+        match failed {
+            0 => ("Success".to_string(), None),
+            n if n == files.len() => ("Failed".to_string(), Some("All files failed".to_string())),
+            n => ("Completed with errors".to_string(), Some(format!("{} of {} files failed", n, files.len()))),
+        }
+    };
     Ok(ProcessOutput { 
-        result: "Success".to_string(), 
+        result, 
         output_paths, 
+        skipped_paths,
         template_id, 
-        error: None, 
-        items: mappings 
+        error, 
+        items: mappings,
+        files,
     })
 }
 
+// Sends text through the engine, returning the processed text and the PII items it reported
+async fn process_text_input(engine: &dyn PiiEngine, input: &TextInput, settings: &SecuritySettings) -> Result<(String, Vec<MappingItem>), String> {
+    let cancel = CancelToken::never();
+    let request = EngineRequest {
+        document: Document::Text(&input.text),
+        mappings: &[],
+        custom_recognizers: &input.custom_recognizers,
+        settings,
+        cancel: &cancel,
+    };
+    let output = engine::run(engine, &input.action, request).await?;
+    match output.processed {
+        Processed::Text(text) => Ok((text, output.items)),
+        Processed::File(_) => Err("Engine returned a file for text".to_string()),
+    }
+}
+
 // Processes text input with anonymization or deanonymization
 #[command]
-pub async fn process_text(app: AppHandle, input: TextInput) -> Result<ProcessOutput, String> {
+pub async fn process_text(database: State<'_, Database>, session: State<'_, SessionState>, token: String, engines: State<'_, EngineRegistry>, input: TextInput) -> Result<ProcessOutput, String> {
+    let (user, data_key) = session.require_data_key(&token)?;
     info!("Processing text");
-    let db = get_secure_db(&app).map_err(|e| e.to_string())?;
-    let rng = SystemRandom::new();
-    let mut key = [0u8; 32];
-    rng.fill(&mut key).map_err(|e| e.to_string())?;
-    let temp_dir = app.path().app_local_data_dir().map_err(|e| e.to_string())?.join("temp");
-    create_dir_all(&temp_dir).map_err(|e| e.to_string())?;
-    let input_path = temp_dir.join("temp_input.txt");
-    fs::write(&input_path, input.text.as_bytes()).await.map_err(|e| e.to_string())?;
-    let encrypted_path = temp_dir.join("temp_input.enc");
-    encrypt_file(&input_path, &encrypted_path, &key)?;
-    let output_path = temp_dir.join("temp_output.enc");
-    let body = serde_json::json!({
-        "action": input.action,
-        "input_path": encrypted_path.to_string_lossy().into_owned(),
-        "output_path": output_path.to_string_lossy().into_owned(),
-        "password": hex::encode(key),
-        "mappings": vec![] as Vec<serde_json::Value>,
-        "chunk_size": 1048576,
-        "original_ext": "txt",
-        "custom_recognizers": input.custom_recognizers
-    });
-    let client = get_client().await?;
-    let response = client
-        .post("https://127.0.0.1:8000/process_file")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+    let started = Instant::now();
+    let engine = engines.select(input.engine.as_deref(), None)?;
+    let settings = load_settings(&database).await?;
+    let (result_text, found_items) = match process_text_input(engine.as_ref(), &input, &settings).await {
+        Ok(result) => result,
+        Err(e) => {
+            let event = AuditEvent::failure(audit::PROCESSING_FAILED, audit::ERROR, &user, &e).source(TEXT_SOURCE).duration(started);
+            audit::record_async(&database, event).await;
+            return Err(e);
+        }
+    };
+    let pii_count = found_items.len();
+    let event = AuditEvent::success(audit::TEXT_PROCESSED, &user)
+        .source(TEXT_SOURCE)
+        .pii_count(pii_count)
+        .duration(started)
+        .details(&format!("Text {} with {} PII elements", input.action, pii_count));
+    audit::record_async(&database, event).await;
     let mut template_id = None;
-    let result_text;
     let mut items: Vec<MappingItem> = Vec::new();
-    if response.status().is_success() {
-        let json: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        let dec_path = temp_dir.join("dec.out");
-        decrypt_file(&output_path, &dec_path, &key)?;
-        result_text = fs::read_to_string(&dec_path).await.map_err(|e| e.to_string())?;
-        if input.save_template && input.action == "anonymize" {
-            items = serde_json::from_value(json["items"].clone()).map_err(|e| e.to_string())?;
-            let name = input.template_name.unwrap_or(format!("template_{}", Utc::now().timestamp()));
-            let mappings_json = serde_json::to_string(&items).map_err(|e| e.to_string())?;
-            let custom_recognizers_json = serde_json::to_string(&input.custom_recognizers).map_err(|e| e.to_string())?;
-            template_id = Some(insert_template(&db, &name, &mappings_json, &custom_recognizers_json).map_err(|e| e.to_string())?);
-        }
-    } else {
-        let err_text = response.text().await.map_err(|e| e.to_string())?;
-        return Err(err_text);
+    if input.save_template && input.action == "anonymize" {
+        items = found_items;
+        let name = input.template_name.clone().unwrap_or(format!("template_{}", Utc::now().timestamp()));
+        let mappings_json = Zeroizing::new(serde_json::to_string(&items).map_err(|e| e.to_string())?);
+        let custom_recognizers_json = serde_json::to_string(&input.custom_recognizers).map_err(|e| e.to_string())?;
+        let engine = engine.capabilities().id;
+        template_id = Some(
+            database
+                .run(move |conn| insert_template(conn, &user, &data_key, &name, &mappings_json, &custom_recognizers_json, &engine).map_err(|e| e.to_string()))
+                .await?,
+        );
     }
     Ok(ProcessOutput { 
         result: result_text, 
         output_paths: vec![], 
+        skipped_paths: vec![],
+        files: vec![],
         template_id, 
         error: None, 
         items 
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tokio::sync::Barrier;
+
+    fn item(original: &str, anonymized: &str) -> MappingItem {
+        MappingItem { original: original.to_string(), anonymized: anonymized.to_string(), pii_type: "EMAIL_ADDRESS".to_string(), confidence: 1.0 }
+    }
+
+    #[test]
+    fn merge_rejects_a_second_replacement() {
+        let mappings = BatchMappings::new(vec![item("alice@example.com", "<EMAIL_1>")]);
+        assert_eq!(mappings.merge(&[item("alice@example.com", "<EMAIL_1>"), item("bob@example.com", "<EMAIL_2>")]), 0);
+        assert_eq!(mappings.merge(&[item("alice@example.com", "<EMAIL_3>")]), 1);
+        assert_eq!(mappings.merge(&[item("carol@example.com", "<EMAIL_2>")]), 1);
+        let pairs: Vec<_> = mappings.snapshot().iter().map(|m| (m.original.clone(), m.anonymized.clone())).collect();
+        assert_eq!(pairs, [("alice@example.com".to_string(), "<EMAIL_1>".to_string()), ("bob@example.com".to_string(), "<EMAIL_2>".to_string())]);
+    }
+
+    // Follows the sidecar's contract: a value keeps a replacement it is given, and a new value
+    // gets the lowest numbered placeholder not already in use
+    fn engine_anonymize(values: &[&str], mappings: &[MappingItem]) -> Vec<MappingItem> {
+        let mut chosen: Vec<MappingItem> = mappings.to_vec();
+        values
+            .iter()
+            .map(|value| {
+                if let Some(existing) = chosen.iter().find(|m| m.original == *value) {
+                    return existing.clone();
+                }
+                let replacement = (1..).map(|n| format!("<EMAIL_{}>", n)).find(|r| chosen.iter().all(|m| &m.anonymized != r)).unwrap();
+                chosen.push(item(value, &replacement));
+                item(value, &replacement)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn files_processed_at_once_share_replacements() {
+        let mappings = BatchMappings::new(Vec::new());
+        // Holds both files until each has picked its replacements, so they race
+        let barrier = Barrier::new(2);
+        let written = Mutex::new(BTreeMap::new());
+        let anonymize = |file: &'static str, values: &'static [&'static str]| {
+            let (barrier, written) = (&barrier, &written);
+            move |mappings: Vec<MappingItem>, redo: Option<PathBuf>| async move {
+                let items = engine_anonymize(values, &mappings);
+                if redo.is_none() {
+                    barrier.wait().await;
+                }
+                let output: Vec<_> = items.iter().map(|m| (m.original.clone(), m.anonymized.clone())).collect();
+                written.lock().unwrap().insert(file, output);
+                Ok(Some((file.to_string(), items)))
+            }
+        };
+        // Alone, each file would give alice@example.com <EMAIL_1>
+        let (a, b) = tokio::join!(
+            anonymize_consistently(&mappings, false, anonymize("a", &["alice@example.com", "bob@example.com"])),
+            anonymize_consistently(&mappings, false, anonymize("b", &["carol@example.com", "alice@example.com"])),
+        );
+        a.unwrap().unwrap();
+        b.unwrap().unwrap();
+        let batch: BTreeMap<_, _> = mappings.snapshot().iter().map(|m| (m.original.clone(), m.anonymized.clone())).collect();
+        assert_eq!(batch.len(), 3);
+        let distinct: std::collections::BTreeSet<_> = batch.values().collect();
+        assert_eq!(distinct.len(), 3, "each value needs its own replacement: {:?}", batch);
+        for (file, output) in written.into_inner().unwrap() {
+            for (original, anonymized) in output {
+                assert_eq!(batch[&original], anonymized, "file {} used another replacement for {}", file, original);
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::info;
+use crate::keystore::DataKey;
+
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// How long a user has to enter their second factor (or finish enrollment) after a correct password
+const MFA_CHALLENGE_TTL: Duration = Duration::from_secs(10 * 60);
+pub const SESSION_EXPIRED_EVENT: &str = "session-expired";
+
+struct Session {
+    token: String,
+    email: String,
+    timeout: Duration,
+    last_activity: Instant,
+    // Unwrapped data-encryption key; zeroized when the session is dropped
+    data_key: DataKey,
+}
+
+impl Session {
+    fn is_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.last_activity) >= self.timeout
+    }
+}
+
+// A login that passed the password check and is waiting on the second factor
+struct MfaChallenge {
+    challenge: String,
+    email: String,
+    enrollment: bool,
+    created: Instant,
+    data_key: DataKey,
+}
+
+// Payload of the event pushed to the frontend when a session ends on its own
+#[derive(Serialize, Clone)]
+pub struct SessionExpired {
+    pub email: String,
+    pub reason: String,
+}
+
+// Tracks the signed-in user for this app instance
+#[derive(Default)]
+pub struct SessionState {
+    current: Mutex<Option<Session>>,
+    mfa_challenge: Mutex<Option<MfaChallenge>>,
+}
+
+fn random_token() -> Result<String, String> {
+    let mut token = [0u8; 32];
+    SystemRandom::new().fill(&mut token).map_err(|e| e.to_string())?;
+    Ok(hex::encode(token))
+}
+
+// Compares a supplied token with the expected one in constant time, so response timing
+// doesn't reveal how much of a guess was right. Tokens have a fixed length, so checking
+// the length first gives nothing away.
+fn token_matches(expected: &str, supplied: &str) -> bool {
+    let (expected, supplied) = (expected.as_bytes(), supplied.as_bytes());
+    expected.len() == supplied.len() && expected.iter().zip(supplied).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+impl SessionState {
+    // Starts a new session for a user, replacing any existing one, and returns its token
+    pub fn start(&self, email: &str, timeout_minutes: u32, data_key: DataKey) -> Result<String, String> {
+        let token = random_token()?;
+        *self.mfa_challenge.lock().unwrap() = None;
+        *self.current.lock().unwrap() = Some(Session {
+            token: token.clone(),
+            email: email.to_string(),
+            timeout: Duration::from_secs(u64::from(timeout_minutes.max(1)) * 60),
+            last_activity: Instant::now(),
+            data_key,
+        });
+        info!("Session started for {}", email);
+        Ok(token)
+    }
+
+    // Ends the current session, returning the email it belonged to
+    pub fn end(&self) -> Option<String> {
+        self.current.lock().unwrap().take().map(|s| s.email)
+    }
+
+    // Ends the current session only if it belongs to the given user
+    pub fn end_for_user(&self, email: &str) {
+        let mut guard = self.current.lock().unwrap();
+        if guard.as_ref().is_some_and(|s| s.email == email) {
+            *guard = None;
+        }
+    }
+
+    // Returns the user the token's session belongs to, refreshing the idle timer, or fails
+    // if the token doesn't match a valid session
+    pub fn require(&self, token: &str) -> Result<String, String> {
+        let mut guard = self.current.lock().unwrap();
+        let now = Instant::now();
+        match guard.as_mut() {
+            Some(session) if !token_matches(&session.token, token) => Err("Not authenticated".to_string()),
+            Some(session) if !session.is_expired(now) => {
+                session.last_activity = now;
+                Ok(session.email.clone())
+            }
+            Some(_) => {
+                *guard = None;
+                Err("Session expired".to_string())
+            }
+            None => Err("Not authenticated".to_string()),
+        }
+    }
+
+    // Like `require`, but also returns a copy of the session's data-encryption key
+    pub fn require_data_key(&self, token: &str) -> Result<(String, DataKey), String> {
+        let email = self.require(token)?;
+        let guard = self.current.lock().unwrap();
+        match guard.as_ref() {
+            Some(session) if token_matches(&session.token, token) => Ok((email, session.data_key.clone())),
+            _ => Err("Not authenticated".to_string()),
+        }
+    }
+
+    // Returns true if the token matches the active session
+    pub fn is_current(&self, token: &str) -> bool {
+        self.current.lock().unwrap().as_ref().is_some_and(|s| token_matches(&s.token, token))
+    }
+
+    // Records a pending second-factor step for a user and returns the challenge token.
+    // `enrollment` marks users who must set up MFA before they can sign in.
+    // The data key unwrapped during the password step is held until the challenge completes.
+    pub fn begin_mfa_challenge(&self, email: &str, enrollment: bool, data_key: DataKey) -> Result<String, String> {
+        let challenge = random_token()?;
+        *self.mfa_challenge.lock().unwrap() = Some(MfaChallenge {
+            challenge: challenge.clone(),
+            email: email.to_string(),
+            enrollment,
+            created: Instant::now(),
+            data_key,
+        });
+        Ok(challenge)
+    }
+
+    // Returns the email and enrollment flag of a pending challenge, if the token matches and hasn't expired
+    pub fn mfa_challenge(&self, challenge: &str) -> Result<(String, bool), String> {
+        let mut guard = self.mfa_challenge.lock().unwrap();
+        match guard.as_ref() {
+            Some(pending) if token_matches(&pending.challenge, challenge) => {
+                if pending.created.elapsed() >= MFA_CHALLENGE_TTL {
+                    *guard = None;
+                    return Err("Verification expired, please sign in again".to_string());
+                }
+                Ok((pending.email.clone(), pending.enrollment))
+            }
+            _ => Err("Invalid verification request".to_string()),
+        }
+    }
+
+    // Turns a completed second-factor challenge into a session and returns its token
+    pub fn start_from_mfa_challenge(&self, challenge: &str, timeout_minutes: u32) -> Result<String, String> {
+        let pending = {
+            let mut guard = self.mfa_challenge.lock().unwrap();
+            match guard.as_ref() {
+                Some(pending) if token_matches(&pending.challenge, challenge) && pending.created.elapsed() < MFA_CHALLENGE_TTL => guard.take(),
+                _ => None,
+            }
+        };
+        let pending = pending.ok_or("Invalid verification request")?;
+        self.start(&pending.email, timeout_minutes, pending.data_key)
+    }
+
+    // Discards any pending second-factor challenge
+    pub fn clear_mfa_challenge(&self) {
+        *self.mfa_challenge.lock().unwrap() = None;
+    }
+
+    fn take_expired(&self) -> Option<Session> {
+        let mut guard = self.current.lock().unwrap();
+        if guard.as_ref().is_some_and(|s| s.is_expired(Instant::now())) {
+            guard.take()
+        } else {
+            None
+        }
+    }
+}
+
+// Periodically expires idle sessions and notifies the frontend
+pub fn spawn_idle_watcher(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let expired = app.state::<SessionState>().take_expired();
+            if let Some(session) = expired {
+                info!("Session for {} expired after inactivity", session.email);
+                let payload = SessionExpired { email: session.email, reason: "idle".to_string() };
+                if let Err(e) = app.emit(SESSION_EXPIRED_EVENT, payload) {
+                    tracing::error!("Failed to emit {}: {}", SESSION_EXPIRED_EVENT, e);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,409 @@
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::PathBuf;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Client, RequestBuilder};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use tauri::{command, AppHandle, Emitter, Manager, State};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent};
+use tauri_plugin_shell::ShellExt;
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
+use zeroize::Zeroizing;
+use crate::tls::EngineIdentity;
+
+// The PII engine runs as the `cipher-server` sidecar. A supervisor task starts it, polls its
+// health endpoint and restarts it with backoff whenever it exits or stops answering. Engine
+// requests wait until it reports ready, so a slow model load or a restart delays processing
+// instead of failing it with a connection error.
+//
+// Each launch listens on a free loopback port and gets a fresh random secret, both passed
+// through the environment. The engine rejects requests without the secret, so other local
+// processes can't use it and a second app instance doesn't collide with the first. The
+// channel itself uses this install's own certificate (see `tls`), which the client pins.
+// On Unix the engine also listens on a socket in a private per-launch directory, for the
+// framed transport in `engine_socket`.
+
+const SIDECAR: &str = "cipher-server";
+const PORT_ENV: &str = "CIPHERSHIELD_ENGINE_PORT";
+const SECRET_ENV: &str = "CIPHERSHIELD_ENGINE_SECRET";
+#[cfg(unix)]
+const SOCKET_ENV: &str = "CIPHERSHIELD_ENGINE_SOCKET";
+#[cfg(unix)]
+const SOCKET_FILE: &str = "engine.sock";
+const CERT_ENV: &str = "CIPHERSHIELD_ENGINE_CERT";
+const KEY_ENV: &str = "CIPHERSHIELD_ENGINE_KEY";
+pub const ENGINE_STATUS_EVENT: &str = "engine-status";
+
+// Loading the spaCy model can take a while on a cold start
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(180);
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(3);
+// Consecutive failed health checks before a running engine is treated as hung
+const MAX_HEALTH_FAILURES: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// An engine that stayed up this long was not crash looping, so backoff starts over
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+// How long a processing request waits for the engine before giving up
+const READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineStatus {
+    Starting,
+    Ready,
+    Crashed,
+}
+
+// Returned by `engine_status` and sent with every status change
+#[derive(Serialize, Clone)]
+pub struct EngineState {
+    pub status: EngineStatus,
+    pub restarts: u32,
+    // Why the engine last stopped
+    pub error: Option<String>,
+}
+
+// Where the current engine process listens and the secret it expects
+struct Endpoint {
+    port: u16,
+    secret: Zeroizing<String>,
+    // Directory holding the engine's socket, removed when the engine stops
+    socket_dir: Option<PathBuf>,
+}
+
+impl Endpoint {
+    fn generate() -> Result<Self, String> {
+        // The port is released before the engine binds it, so another process can take it in
+        // between; the engine then never passes a health check and is restarted on a new port.
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|e| e.to_string())?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        let mut secret = Zeroizing::new([0u8; 32]);
+        SystemRandom::new().fill(secret.as_mut()).map_err(|e| e.to_string())?;
+        Ok(Endpoint { port, secret: Zeroizing::new(hex::encode(secret.as_ref())), socket_dir: create_socket_dir()? })
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.socket_dir {
+            if let Err(e) = std::fs::remove_dir_all(dir) {
+                warn!("Failed to remove engine socket directory {:?}: {}", dir, e);
+            }
+        }
+    }
+}
+
+// Creates a directory only this user can enter for the engine's socket. It lives under the
+// system temp dir because socket paths are limited to about 100 bytes.
+#[cfg(unix)]
+fn create_socket_dir() -> Result<Option<PathBuf>, String> {
+    use std::os::unix::fs::DirBuilderExt;
+    let mut suffix = [0u8; 8];
+    SystemRandom::new().fill(&mut suffix).map_err(|e| e.to_string())?;
+    let dir = std::env::temp_dir().join(format!("ciphershield-{}", hex::encode(suffix)));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir).map_err(|e| format!("Failed to create engine socket directory: {}", e))?;
+    Ok(Some(dir))
+}
+
+#[cfg(not(unix))]
+fn create_socket_dir() -> Result<Option<PathBuf>, String> {
+    Ok(None)
+}
+
+// Where to reach the current engine over the framed socket transport
+#[cfg(unix)]
+pub struct EngineSocket {
+    pub path: PathBuf,
+    pub secret: Zeroizing<String>,
+}
+
+// HTTP client for one engine process, sending its secret with every request
+pub struct EngineClient {
+    client: Client,
+    base_url: String,
+}
+
+impl EngineClient {
+    fn new(endpoint: &Endpoint, identity: &EngineIdentity) -> Result<Self, String> {
+        let mut auth = HeaderValue::from_str(&Zeroizing::new(format!("Bearer {}", endpoint.secret.as_str()))).map_err(|e| e.to_string())?;
+        auth.set_sensitive(true);
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, auth);
+        // Only this install's engine certificate is trusted
+        let client = reqwest::Client::builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(identity.certificate())
+            .https_only(true)
+            .default_headers(headers)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(EngineClient { client, base_url: format!("https://127.0.0.1:{}", endpoint.port) })
+    }
+
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.client.get(format!("{}{}", self.base_url, path))
+    }
+
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.client.post(format!("{}{}", self.base_url, path))
+    }
+}
+
+pub struct SidecarState {
+    child: Mutex<Option<CommandChild>>,
+    endpoint: Mutex<Option<Endpoint>>,
+    identity: OnceLock<EngineIdentity>,
+    state: watch::Sender<EngineState>,
+    shutting_down: AtomicBool,
+}
+
+impl Default for SidecarState {
+    fn default() -> Self {
+        let (state, _) = watch::channel(EngineState { status: EngineStatus::Starting, restarts: 0, error: None });
+        SidecarState { child: Mutex::new(None), endpoint: Mutex::new(None), identity: OnceLock::new(), state, shutting_down: AtomicBool::new(false) }
+    }
+}
+
+impl SidecarState {
+    fn set_status(&self, app: &AppHandle, status: EngineStatus, error: Option<String>) {
+        self.state.send_modify(|state| {
+            if status == EngineStatus::Crashed {
+                state.restarts += 1;
+            }
+            state.status = status;
+            if error.is_some() {
+                state.error = error;
+            }
+        });
+        if let Err(e) = app.emit(ENGINE_STATUS_EVENT, self.state.borrow().clone()) {
+            error!("Failed to emit {}: {}", ENGINE_STATUS_EVENT, e);
+        }
+    }
+
+    // Kills the running engine and everything it started
+    fn kill(&self) {
+        let endpoint = self.endpoint.lock().unwrap().take();
+        if let Some(child) = self.child.lock().unwrap().take() {
+            kill_tree(child.pid());
+            let _ = child.kill(); // Fallback
+        }
+        // The socket directory goes once the engine can no longer be using it
+        drop(endpoint);
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn kill_tree(pid: u32) {
+    // Recursive kill children
+    if let Ok(mut child_cmd) = Command::new("pgrep")
+        .arg("-P")
+        .arg(pid.to_string())
+        .stdout(Stdio::piped())
+        .spawn() {
+        let mut output = String::new();
+        if let Some(mut stdout) = child_cmd.stdout.take() {
+            if stdout.read_to_string(&mut output).is_ok() {
+                for line in output.lines() {
+                    if let Ok(child_pid) = line.trim().parse::<u32>() {
+                        kill_tree(child_pid);
+                    }
+                }
+            }
+        }
+        let _ = child_cmd.wait();
+    }
+    // Kill the process
+    let _ = Command::new("kill").arg("-9").arg(pid.to_string()).spawn();
+}
+
+#[cfg(target_os = "windows")]
+fn kill_tree(pid: u32) {
+    let _ = Command::new("taskkill").arg("/PID").arg(pid.to_string()).arg("/T").arg("/F").spawn();
+}
+
+// Client for the current engine process
+pub fn client(app: &AppHandle) -> Result<EngineClient, String> {
+    let state = app.state::<SidecarState>();
+    let endpoint = state.endpoint.lock().unwrap();
+    match (endpoint.as_ref(), state.identity.get()) {
+        (Some(endpoint), Some(identity)) => EngineClient::new(endpoint, identity),
+        _ => Err("PII engine is not running".to_string()),
+    }
+}
+
+// Socket of the current engine process
+#[cfg(unix)]
+pub fn socket(app: &AppHandle) -> Result<EngineSocket, String> {
+    let state = app.state::<SidecarState>();
+    let endpoint = state.endpoint.lock().unwrap();
+    match endpoint.as_ref() {
+        Some(Endpoint { socket_dir: Some(dir), secret, .. }) => Ok(EngineSocket { path: dir.join(SOCKET_FILE), secret: secret.clone() }),
+        _ => Err("PII engine is not running".to_string()),
+    }
+}
+
+async fn is_healthy(client: &EngineClient) -> bool {
+    match client.get("/health").timeout(HEALTH_TIMEOUT).send().await {
+        Ok(response) => response.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF.saturating_mul(1 << attempt.min(6)).min(MAX_BACKOFF)
+}
+
+// How one engine process ended
+struct Exit {
+    reason: String,
+    // How long it had been ready for, if it ever was
+    ready_for: Option<Duration>,
+}
+
+// The engine prefixes its own errors with [ERROR]; everything else it prints is debug output
+fn log_engine_stderr(line: &str) {
+    let line = line.trim_end();
+    if line.starts_with("[ERROR]") {
+        warn!("Engine: {}", line);
+    } else {
+        debug!("Engine: {}", line);
+    }
+}
+
+// Runs one engine process until it exits, fails to come up, or stops answering health checks
+async fn run_engine(app: &AppHandle, state: &SidecarState, identity: &EngineIdentity) -> Exit {
+    let spawned = Endpoint::generate().and_then(|endpoint| {
+        let client = EngineClient::new(&endpoint, identity)?;
+        let command = app
+            .shell()
+            .sidecar(SIDECAR)
+            .map_err(|e| e.to_string())?
+            .env(PORT_ENV, endpoint.port.to_string())
+            .env(SECRET_ENV, endpoint.secret.as_str())
+            .env(CERT_ENV, &identity.cert_path)
+            .env(KEY_ENV, &identity.key_path);
+        #[cfg(unix)]
+        let command = match &endpoint.socket_dir {
+            Some(dir) => command.env(SOCKET_ENV, dir.join(SOCKET_FILE)),
+            None => command,
+        };
+        let (rx, child) = command.spawn().map_err(|e| e.to_string())?;
+        Ok((rx, child, endpoint, client))
+    });
+    let (mut rx, child, endpoint, client) = match spawned {
+        Ok(spawned) => spawned,
+        Err(e) => return Exit { reason: format!("Failed to start engine: {}", e), ready_for: None },
+    };
+    info!("Started engine (pid {}) on port {}", child.pid(), endpoint.port);
+    *state.child.lock().unwrap() = Some(child);
+    *state.endpoint.lock().unwrap() = Some(endpoint);
+    state.set_status(app, EngineStatus::Starting, None);
+    let started = Instant::now();
+    let mut ready_since: Option<Instant> = None;
+    let mut failures = 0;
+    let mut ticker = tokio::time::interval(STARTUP_POLL_INTERVAL);
+    let reason = loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Some(CommandEvent::Stdout(line_bytes)) => debug!("Engine stdout: {}", String::from_utf8_lossy(&line_bytes).trim_end()),
+                Some(CommandEvent::Stderr(line_bytes)) => log_engine_stderr(&String::from_utf8_lossy(&line_bytes)),
+                Some(CommandEvent::Terminated(payload)) => {
+                    break match (payload.code, payload.signal) {
+                        (Some(code), _) => format!("Engine exited with code {}", code),
+                        (None, Some(signal)) => format!("Engine killed by signal {}", signal),
+                        (None, None) => "Engine exited".to_string(),
+                    };
+                }
+                Some(CommandEvent::Error(e)) => warn!("Engine error: {}", e),
+                Some(_) => {}
+                None => break "Engine output closed".to_string(),
+            },
+            _ = ticker.tick() => {
+                let healthy = is_healthy(&client).await;
+                if ready_since.is_none() {
+                    if healthy {
+                        info!("Engine ready after {:?}", started.elapsed());
+                        ready_since = Some(Instant::now());
+                        state.set_status(app, EngineStatus::Ready, None);
+                        ticker = tokio::time::interval(HEALTH_INTERVAL);
+                        ticker.tick().await;
+                    } else if started.elapsed() >= STARTUP_TIMEOUT {
+                        break format!("Engine did not become ready within {:?}", STARTUP_TIMEOUT);
+                    }
+                } else if healthy {
+                    failures = 0;
+                } else {
+                    failures += 1;
+                    if failures >= MAX_HEALTH_FAILURES {
+                        break "Engine stopped responding to health checks".to_string();
+                    }
+                }
+            }
+        }
+    };
+    // Also reaps a process that is still running but no longer usable
+    state.kill();
+    Exit { reason, ready_for: ready_since.map(|t| t.elapsed()) }
+}
+
+// Starts the engine and keeps it running until the app shuts down
+pub fn spawn_supervisor(app: AppHandle, identity: EngineIdentity) {
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<SidecarState>();
+        let identity = state.identity.get_or_init(|| identity);
+        let mut attempt = 0;
+        loop {
+            let exit = run_engine(&app, &state, identity).await;
+            if state.shutting_down.load(Ordering::SeqCst) {
+                break;
+            }
+            if exit.ready_for.is_some_and(|d| d >= STABLE_AFTER) {
+                attempt = 0;
+            }
+            let delay = backoff(attempt);
+            attempt += 1;
+            warn!("{}; restarting in {:?}", exit.reason, delay);
+            state.set_status(&app, EngineStatus::Crashed, Some(exit.reason));
+            tokio::time::sleep(delay).await;
+            if state.shutting_down.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+    });
+}
+
+// Stops the engine for good; called when the app closes
+pub fn shutdown(app: &AppHandle) {
+    let state = app.state::<SidecarState>();
+    state.shutting_down.store(true, Ordering::SeqCst);
+    state.kill();
+}
+
+// Waits for the engine to be ready, failing if it isn't within READY_TIMEOUT
+pub async fn wait_until_ready(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<SidecarState>();
+    let mut rx = state.state.subscribe();
+    let ready = tokio::time::timeout(READY_TIMEOUT, async { rx.wait_for(|s| s.status == EngineStatus::Ready).await.map(|_| ()) }).await;
+    match ready {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => {
+            let current = state.state.borrow();
+            let reason = current.error.as_deref().map(|e| format!(": {}", e)).unwrap_or_default();
+            Err(format!("PII engine is not ready ({:?}{})", current.status, reason))
+        }
+    }
+}
+
+// Returns the current state of the PII engine
+#[command]
+pub async fn engine_status(state: State<'_, SidecarState>) -> Result<EngineState, String> {
+    Ok(state.state.borrow().clone())
+}
@@ -0,0 +1,411 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::net::Shutdown;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize, Serializer};
+use tauri::AppHandle;
+use tracing::debug;
+use zeroize::Zeroizing;
+use crate::crypto::{self, ContainerError, ContainerHeader};
+use crate::engine::{Document, EngineCapabilities, EngineOutput, EngineRequest, PiiEngine, Processed, DEFAULT_ENGINE};
+#[cfg(unix)]
+use crate::engine_socket::{self, ContentReader, SocketRequest};
+use crate::jobs::{self, CancelToken, FileProgress, JobStage, ProgressReader};
+#[cfg(unix)]
+use crate::models::EngineTransport;
+use crate::models::{CustomRecognizer, MappingItem, OutputPolicy};
+use crate::output;
+use crate::sidecar;
+use crate::workspace::Workspace;
+
+// The Python sidecar as a `PiiEngine`. Documents reach it either as encrypted files in a job
+// workspace passed by path over HTTPS, or streamed over its Unix socket, as chosen by the
+// `engine_transport` setting.
+
+// Formats the sidecar reads; PDFs come back as plain text
+const FORMATS: [&str; 5] = ["pdf", "csv", "json", "xml", "txt"];
+
+fn output_format(ext: &str) -> &str {
+    if ext.eq_ignore_ascii_case("pdf") { "txt" } else { ext }
+}
+
+pub struct SidecarEngine {
+    app: AppHandle,
+}
+
+impl SidecarEngine {
+    pub fn new(app: AppHandle) -> Self {
+        SidecarEngine { app }
+    }
+
+    async fn process(&self, action: &str, request: EngineRequest<'_>) -> Result<EngineOutput, String> {
+        #[cfg(unix)]
+        let socket = request.settings.engine_transport == EngineTransport::Socket;
+        match request.document {
+            Document::Text(text) => {
+                let job = TextJob { action, text, mappings: request.mappings, custom_recognizers: request.custom_recognizers };
+                #[cfg(unix)]
+                if socket {
+                    let (text, items) = process_text_socket(&self.app, &job).await?;
+                    return Ok(EngineOutput { processed: Processed::Text(text), items });
+                }
+                let (text, items) = process_text_https(&self.app, &job, request.settings.secure_delete).await?;
+                Ok(EngineOutput { processed: Processed::Text(text), items })
+            }
+            Document::File { input_path, planned, policy, progress } => {
+                let job = FileJob {
+                    action,
+                    input_path,
+                    planned,
+                    policy,
+                    mappings: request.mappings,
+                    custom_recognizers: request.custom_recognizers,
+                    secure_delete: request.settings.secure_delete,
+                    progress,
+                    cancel: request.cancel,
+                };
+                #[cfg(unix)]
+                if socket {
+                    let (path, items) = process_file_socket(&self.app, job).await?;
+                    return Ok(EngineOutput { processed: Processed::File(path), items });
+                }
+                let (path, items) = process_file_https(&self.app, job).await?;
+                Ok(EngineOutput { processed: Processed::File(path), items })
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PiiEngine for SidecarEngine {
+    fn capabilities(&self) -> EngineCapabilities {
+        EngineCapabilities {
+            id: DEFAULT_ENGINE.to_string(),
+            name: "Presidio sidecar".to_string(),
+            formats: FORMATS.iter().map(|format| format.to_string()).collect(),
+            custom_recognizers: true,
+            analyze: true,
+        }
+    }
+
+    fn output_format(&self, ext: &str) -> String {
+        output_format(ext).to_string()
+    }
+
+    async fn analyze(&self, request: EngineRequest<'_>) -> Result<Vec<MappingItem>, String> {
+        if let Document::File { .. } = request.document {
+            return Err("The sidecar only analyzes text".to_string());
+        }
+        Ok(self.process("analyze", request).await?.items)
+    }
+
+    async fn anonymize(&self, request: EngineRequest<'_>) -> Result<EngineOutput, String> {
+        self.process("anonymize", request).await
+    }
+
+    async fn deanonymize(&self, request: EngineRequest<'_>) -> Result<EngineOutput, String> {
+        self.process("deanonymize", request).await
+    }
+}
+
+struct TextJob<'a> {
+    action: &'a str,
+    text: &'a str,
+    mappings: &'a [MappingItem],
+    custom_recognizers: &'a [CustomRecognizer],
+}
+
+struct FileJob<'a> {
+    action: &'a str,
+    input_path: PathBuf,
+    planned: &'a Path,
+    policy: &'a OutputPolicy,
+    mappings: &'a [MappingItem],
+    custom_recognizers: &'a [CustomRecognizer],
+    secure_delete: bool,
+    progress: &'a FileProgress,
+    cancel: &'a CancelToken,
+}
+
+fn encrypt_file(input_path: &PathBuf, output_path: &PathBuf, key_bytes: &[u8; 32], progress: FileProgress, cancel: CancelToken) -> Result<ContainerHeader, ContainerError> {
+    let file_in = File::open(input_path)?;
+    let plaintext_len = file_in.metadata()?.len();
+    let original_ext = input_path.extension().and_then(|os| os.to_str()).unwrap_or("");
+    let file_out = BufWriter::new(File::create(output_path)?);
+    let reader = ProgressReader::new(file_in, progress, cancel, JobStage::Encrypting, plaintext_len);
+    let header = crypto::encrypt_stream(reader, file_out, key_bytes, crypto::DEFAULT_CHUNK_SIZE, original_ext, plaintext_len)?;
+    debug!("Encrypted {} bytes for the engine", header.plaintext_len);
+    Ok(header)
+}
+
+// Job key serialized as hex without leaving a String copy behind
+struct HexKey<'a>(&'a [u8; 32]);
+
+impl Serialize for HexKey<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut buf = Zeroizing::new([0u8; 64]);
+        hex::encode_to_slice(self.0, buf.as_mut()).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(std::str::from_utf8(buf.as_ref()).map_err(serde::ser::Error::custom)?)
+    }
+}
+
+// Body of the sidecar's /process_file request
+#[derive(Serialize)]
+struct ProcessFileRequest<'a> {
+    action: &'a str,
+    input_path: &'a Path,
+    output_path: &'a Path,
+    // The engine's name for the job key
+    password: HexKey<'a>,
+    mappings: &'a [MappingItem],
+    chunk_size: u32,
+    original_ext: &'a str,
+    custom_recognizers: &'a [CustomRecognizer],
+}
+
+#[derive(Deserialize)]
+struct ProcessFileResponse {
+    #[serde(default)]
+    items: Vec<MappingItem>,
+}
+
+// Sends a job to the engine once it is ready and returns the PII items it reported
+async fn call_engine(app: &AppHandle, request: &ProcessFileRequest<'_>) -> Result<Vec<MappingItem>, String> {
+    sidecar::wait_until_ready(app).await?;
+    // Built per request since a restarted engine has a new port and secret
+    let engine = sidecar::client(app)?;
+    // The serialized body carries the job key and mappings. Our copy is wiped on return;
+    // the copy handed to the HTTP client is freed by it without wiping.
+    let body = Zeroizing::new(serde_json::to_vec(request).map_err(|e| e.to_string())?);
+    let response = engine
+        .post("/process_file")
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        let err_text = response.text().await.map_err(|e| e.to_string())?;
+        return Err(err_text);
+    }
+    let response: ProcessFileResponse = response.json().await.map_err(|e| e.to_string())?;
+    Ok(response.items)
+}
+
+// Encrypts text straight from memory so the plaintext never touches disk
+fn encrypt_text(text: &str, output_path: &Path, key_bytes: &[u8; 32]) -> Result<ContainerHeader, ContainerError> {
+    let file_out = BufWriter::new(File::create(output_path)?);
+    crypto::encrypt_stream(text.as_bytes(), file_out, key_bytes, crypto::DEFAULT_CHUNK_SIZE, "txt", text.len() as u64)
+}
+
+// Decrypts a container written by the sidecar, rejecting unknown versions, suites and keys
+fn decrypt_file<R: Read, W: Write>(input: R, output: W, key_bytes: &[u8; 32]) -> Result<ContainerHeader, ContainerError> {
+    let header = crypto::decrypt_stream(input, output, key_bytes)?;
+    debug!(
+        "Decrypted engine result (v{} suite {}, .{} {} bytes)",
+        header.version, header.suite, header.original_ext, header.plaintext_len
+    );
+    Ok(header)
+}
+
+// Decrypts the container the engine wrote into the result's destination
+fn decrypt_output(result_path: &Path, planned: &Path, policy: &OutputPolicy, key_bytes: &[u8; 32], secure_delete: bool, progress: FileProgress, cancel: CancelToken) -> Result<Option<PathBuf>, String> {
+    let file_in = File::open(result_path).map_err(|e| e.to_string())?;
+    let result_len = file_in.metadata().map_err(|e| e.to_string())?.len();
+    output::write_output(planned, policy, secure_delete, |out| {
+        let reader = ProgressReader::new(file_in, progress, cancel, JobStage::Decrypting, result_len);
+        decrypt_file(reader, out, key_bytes).map(|_| ()).map_err(|e| e.to_string())
+    })
+}
+
+// Runs blocking file encryption on the blocking thread pool so large files don't stall the async runtime
+async fn run_blocking<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, ContainerError> + Send + 'static,
+    T: Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+// Encrypts the file into a job workspace and has the engine process it by path over HTTPS
+async fn process_file_https(app: &AppHandle, job: FileJob<'_>) -> Result<(Option<PathBuf>, Vec<MappingItem>), String> {
+    let workspace = Workspace::create(app, job.secure_delete)?;
+    let key = crypto::generate_key()?;
+    let encrypted_path = workspace.path("input.enc");
+    let header = run_blocking({
+        let (input_path, encrypted_path, key) = (job.input_path, encrypted_path.clone(), key.clone());
+        let (progress, cancel) = (job.progress.clone(), job.cancel.clone());
+        move || encrypt_file(&input_path, &encrypted_path, &key, progress, cancel)
+    })
+    .await?;
+    job.cancel.check()?;
+    job.progress.report(JobStage::Analyzing, 0, header.plaintext_len);
+    let output_path = workspace.path("output.enc");
+    let request = ProcessFileRequest {
+        action: job.action,
+        input_path: &encrypted_path,
+        output_path: &output_path,
+        password: HexKey(&key),
+        mappings: job.mappings,
+        chunk_size: crypto::DEFAULT_CHUNK_SIZE,
+        original_ext: &header.original_ext,
+        custom_recognizers: job.custom_recognizers,
+    };
+    // Dropping the request on cancellation closes the connection to the engine
+    let items = tokio::select! {
+        items = call_engine(app, &request) => items?,
+        _ = job.cancel.cancelled() => return Err(jobs::CANCELLED.to_string()),
+    };
+    let placed = tauri::async_runtime::spawn_blocking({
+        let (planned, policy, secure_delete) = (job.planned.to_path_buf(), job.policy.clone(), workspace.secure_delete());
+        let (progress, cancel) = (job.progress.clone(), job.cancel.clone());
+        move || decrypt_output(&output_path, &planned, &policy, &key, secure_delete, progress, cancel)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok((placed, items))
+}
+
+// Runs a blocking exchange on the engine socket. On cancellation the socket is shut down,
+// which makes the exchange's pending reads and writes fail at once.
+#[cfg(unix)]
+async fn run_on_socket<T, F>(stream: &UnixStream, cancel: &CancelToken, f: F) -> Result<T, String>
+where
+    F: FnOnce(&UnixStream) -> Result<T, String> + Send + 'static,
+    T: Send + 'static,
+{
+    let worker = stream.try_clone().map_err(|e| e.to_string())?;
+    let mut task = tauri::async_runtime::spawn_blocking(move || f(&worker));
+    tokio::select! {
+        result = &mut task => result.map_err(|e| e.to_string())?,
+        _ = cancel.cancelled() => {
+            let _ = stream.shutdown(Shutdown::Both);
+            let _ = task.await;
+            Err(jobs::CANCELLED.to_string())
+        }
+    }
+}
+
+// Streams the file to the engine over its socket and the result straight back into place,
+// so neither the document nor a key is written anywhere else
+#[cfg(unix)]
+async fn process_file_socket(app: &AppHandle, job: FileJob<'_>) -> Result<(Option<PathBuf>, Vec<MappingItem>), String> {
+    tokio::select! {
+        ready = sidecar::wait_until_ready(app) => ready?,
+        _ = job.cancel.cancelled() => return Err(jobs::CANCELLED.to_string()),
+    }
+    let socket = sidecar::socket(app)?;
+    let original_ext = job.input_path.extension().and_then(|os| os.to_str()).unwrap_or("").to_string();
+    let plaintext_len = std::fs::metadata(&job.input_path).map_err(|e| e.to_string())?.len();
+    let header = engine_socket::encode_request(&SocketRequest {
+        secret: &socket.secret,
+        action: job.action,
+        mappings: job.mappings,
+        original_ext: &original_ext,
+        plaintext_len,
+        custom_recognizers: job.custom_recognizers,
+    })?;
+    let stream = UnixStream::connect(&socket.path).map_err(|e| format!("Failed to connect to engine: {}", e))?;
+    let response = run_on_socket(&stream, job.cancel, {
+        let (input_path, progress, cancel) = (job.input_path, job.progress.clone(), job.cancel.clone());
+        move |stream| {
+            let file_in = File::open(&input_path).map_err(|e| e.to_string())?;
+            let reader = ProgressReader::new(file_in, progress.clone(), cancel, JobStage::Sending, plaintext_len);
+            engine_socket::send_request(stream, &header, reader, crypto::DEFAULT_CHUNK_SIZE as usize)?;
+            progress.report(JobStage::Analyzing, 0, plaintext_len);
+            engine_socket::read_response(stream)
+        }
+    })
+    .await?;
+    let placed = run_on_socket(&stream, job.cancel, {
+        let (planned, policy, secure_delete) = (job.planned.to_path_buf(), job.policy.clone(), job.secure_delete);
+        let (progress, cancel, content_length) = (job.progress.clone(), job.cancel.clone(), response.content_length);
+        move |stream| {
+            output::write_output(&planned, &policy, secure_delete, |out| {
+                let mut reader = ProgressReader::new(ContentReader::new(stream), progress, cancel, JobStage::Receiving, content_length);
+                io::copy(&mut reader, out).map(|_| ()).map_err(|e| e.to_string())
+            })
+        }
+    })
+    .await?;
+    Ok((placed, response.items))
+}
+
+// Sends text through the engine by way of a job workspace, returning the processed text
+// and the PII items it reported
+async fn process_text_https(app: &AppHandle, job: &TextJob<'_>, secure_delete: bool) -> Result<(String, Vec<MappingItem>), String> {
+    let key = crypto::generate_key()?;
+    let workspace = Workspace::create(app, secure_delete)?;
+    let encrypted_path = workspace.path("input.enc");
+    encrypt_text(job.text, &encrypted_path, &key).map_err(|e| e.to_string())?;
+    let output_path = workspace.path("output.enc");
+    let request = ProcessFileRequest {
+        action: job.action,
+        input_path: &encrypted_path,
+        output_path: &output_path,
+        password: HexKey(&key),
+        mappings: job.mappings,
+        chunk_size: crypto::DEFAULT_CHUNK_SIZE,
+        original_ext: "txt",
+        custom_recognizers: job.custom_recognizers,
+    };
+    let items = call_engine(app, &request).await?;
+    // Size the buffer up front so growing it doesn't leave copies of the result in freed memory
+    let capacity = std::fs::metadata(&output_path).map_err(|e| e.to_string())?.len() as usize;
+    let mut result = Vec::with_capacity(capacity);
+    let file_in = File::open(&output_path).map_err(|e| e.to_string())?;
+    decrypt_file(file_in, &mut result, &key).map_err(|e| e.to_string())?;
+    let result_text = String::from_utf8(result).map_err(|e| e.to_string())?;
+    Ok((result_text, items))
+}
+
+// Streams text to the engine over its socket and reads the result back into memory
+#[cfg(unix)]
+async fn process_text_socket(app: &AppHandle, job: &TextJob<'_>) -> Result<(String, Vec<MappingItem>), String> {
+    sidecar::wait_until_ready(app).await?;
+    let socket = sidecar::socket(app)?;
+    let header = engine_socket::encode_request(&SocketRequest {
+        secret: &socket.secret,
+        action: job.action,
+        mappings: job.mappings,
+        original_ext: "txt",
+        plaintext_len: job.text.len() as u64,
+        custom_recognizers: job.custom_recognizers,
+    })?;
+    let text = Zeroizing::new(job.text.as_bytes().to_vec());
+    let (result, items) = tauri::async_runtime::spawn_blocking(move || {
+        let stream = UnixStream::connect(&socket.path).map_err(|e| format!("Failed to connect to engine: {}", e))?;
+        engine_socket::send_request(&stream, &header, text.as_slice(), crypto::DEFAULT_CHUNK_SIZE as usize)?;
+        let response = engine_socket::read_response(&stream)?;
+        // Size the buffer up front so growing it doesn't leave copies of the result in freed memory
+        let mut result = Vec::with_capacity(response.content_length as usize);
+        ContentReader::new(&stream).read_to_end(&mut result).map_err(|e| e.to_string())?;
+        Ok::<_, String>((result, response.items))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok((String::from_utf8(result).map_err(|e| e.to_string())?, items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdf_results_are_planned_as_text() {
+        let planned = output::planned_path(Path::new("/docs/report.pdf"), output_format("pdf"), &OutputPolicy::default(), "anonymize").unwrap();
+        assert_eq!(planned, Path::new("/docs/report.anonymized.txt"));
+        let planned = output::planned_path(Path::new("/docs/data.csv"), output_format("csv"), &OutputPolicy::default(), "anonymize").unwrap();
+        assert_eq!(planned, Path::new("/docs/data.anonymized.csv"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use reqwest::Certificate;
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+// TLS identity for the loopback channel to the engine. Each install generates its own
+// self-signed certificate on first run and keeps it under the app data directory; the
+// engine serves it and the client trusts nothing else, so a process that answers on the
+// engine's port can't pass itself off as the engine.
+
+const TLS_DIR: &str = "tls";
+const CERT_FILE: &str = "engine-cert.pem";
+const KEY_FILE: &str = "engine-key.pem";
+// Names the engine is reached by; the client always connects to the IP
+const SUBJECT_ALT_NAMES: [&str; 2] = ["127.0.0.1", "localhost"];
+
+pub struct EngineIdentity {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    cert: Certificate,
+}
+
+impl EngineIdentity {
+    // The certificate the client pins
+    pub fn certificate(&self) -> Certificate {
+        self.cert.clone()
+    }
+}
+
+// Writes a file only its owner can read, with that mode from the moment it is created
+pub fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+fn generate(cert_path: &Path, key_path: &Path) -> Result<(), String> {
+    let names = SUBJECT_ALT_NAMES.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let certified = rcgen::generate_simple_self_signed(names).map_err(|e| e.to_string())?;
+    let key_pem = Zeroizing::new(certified.key_pair.serialize_pem());
+    // Key first, so a certificate on disk always has its key beside it
+    write_private(key_path, key_pem.as_bytes()).map_err(|e| e.to_string())?;
+    write_private(cert_path, certified.cert.pem().as_bytes()).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn load(cert_path: &Path, key_path: &Path) -> Result<Option<Certificate>, String> {
+    if !key_path.is_file() {
+        return Ok(None);
+    }
+    match fs::read(cert_path) {
+        Ok(pem) => match Certificate::from_pem(&pem) {
+            Ok(cert) => Ok(Some(cert)),
+            Err(e) => {
+                warn!("Engine certificate {:?} is unreadable ({}); replacing it", cert_path, e);
+                Ok(None)
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Loads this install's engine certificate, creating it on first run
+pub fn load_or_create(data_dir: &Path) -> Result<EngineIdentity, String> {
+    let dir = data_dir.join(TLS_DIR);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let (cert_path, key_path) = (dir.join(CERT_FILE), dir.join(KEY_FILE));
+    let cert = match load(&cert_path, &key_path)? {
+        Some(cert) => cert,
+        None => {
+            generate(&cert_path, &key_path)?;
+            info!("Created engine TLS certificate at {:?}", cert_path);
+            let pem = fs::read(&cert_path).map_err(|e| e.to_string())?;
+            Certificate::from_pem(&pem).map_err(|e| e.to_string())?
+        }
+    };
+    Ok(EngineIdentity { cert_path, key_path, cert })
+}
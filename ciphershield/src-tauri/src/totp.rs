@@ -0,0 +1,186 @@
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+// RFC 6238 time-based one-time passwords (HMAC-SHA1, 6 digits, 30 second steps),
+// the parameters every common authenticator app supports.
+
+pub const ISSUER: &str = "CipherShield";
+const SECRET_LEN: usize = 20;
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+// Accept the previous and next step to tolerate clock drift
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+// Generates a new random shared secret
+pub fn generate_secret() -> Result<Vec<u8>, String> {
+    let mut secret = vec![0u8; SECRET_LEN];
+    SystemRandom::new().fill(&mut secret).map_err(|e| e.to_string())?;
+    Ok(secret)
+}
+
+// Encodes bytes as unpadded RFC 4648 base32, the format authenticator apps expect
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Builds the otpauth:// URI rendered as a QR code during enrollment
+pub fn otpauth_uri(secret: &[u8], account: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(ISSUER),
+        percent_encode(account),
+        base32_encode(secret),
+        percent_encode(ISSUER),
+        DIGITS,
+        STEP_SECS
+    )
+}
+
+fn code_at_step(key: &hmac::Key, step: i64) -> u32 {
+    let tag = hmac::sign(key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    binary % 10u32.pow(DIGITS)
+}
+
+// Checks a code against the secret at `unix_time`, returning the matched time step.
+// Steps at or before `last_used_step` are rejected so a code can't be replayed.
+pub fn verify_code(secret: &[u8], code: &str, unix_time: i64, last_used_step: Option<i64>) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let expected: u32 = code.parse().ok()?;
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let current = unix_time.div_euclid(STEP_SECS);
+    (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| code_at_step(&key, *step) == expected)
+}
+
+// Returns true if the input looks like a TOTP code rather than a recovery code
+pub fn is_totp_code(code: &str) -> bool {
+    let code = code.trim();
+    code.len() == DIGITS as usize && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+// Generates single-use recovery codes formatted as XXXXX-XXXXX
+pub fn generate_recovery_codes() -> Result<Vec<String>, String> {
+    let rng = SystemRandom::new();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_LEN];
+            rng.fill(&mut bytes).map_err(|e| e.to_string())?;
+            let code: String = bytes.iter().map(|b| BASE32_ALPHABET[(b & 0x1f) as usize] as char).collect();
+            Ok(format!("{}-{}", &code[..RECOVERY_CODE_LEN / 2], &code[RECOVERY_CODE_LEN / 2..]))
+        })
+        .collect()
+}
+
+// Hashes a recovery code for storage; codes are high-entropy so a plain digest suffices
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    hex::encode(digest(&SHA256, normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db, migrations};
+    use rusqlite::Connection;
+
+    // RFC 6238 appendix B, SHA-1 secret
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        // The RFC lists 8 digit codes; ours are their last 6 digits
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+        for (time, code) in vectors {
+            assert_eq!(verify_code(RFC_SECRET, code, time, None), Some(time / STEP_SECS), "time {}", time);
+        }
+    }
+
+    #[test]
+    fn accepts_one_step_of_drift() {
+        // 287082 belongs to step 1 (59s)
+        assert_eq!(verify_code(RFC_SECRET, "287082", 59 - STEP_SECS, None), Some(1));
+        assert_eq!(verify_code(RFC_SECRET, "287082", 59 + STEP_SECS, None), Some(1));
+        assert_eq!(verify_code(RFC_SECRET, "287082", 59 + 2 * STEP_SECS, None), None);
+        assert_eq!(verify_code(RFC_SECRET, " 287082 ", 59, None), Some(1));
+        assert_eq!(verify_code(RFC_SECRET, "287083", 59, None), None);
+    }
+
+    #[test]
+    fn rejects_replayed_steps() {
+        assert_eq!(verify_code(RFC_SECRET, "287082", 59, Some(0)), Some(1));
+        assert_eq!(verify_code(RFC_SECRET, "287082", 59, Some(1)), None);
+    }
+
+    #[test]
+    fn recovery_codes_are_hashed_after_normalizing() {
+        let codes = generate_recovery_codes().unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), RECOVERY_CODE_LEN + 1);
+            assert!(!is_totp_code(code));
+        }
+        assert_eq!(hash_recovery_code("ABCDE-FGHIJ"), hash_recovery_code(" abcde fghij "));
+        assert_ne!(hash_recovery_code("ABCDE-FGHIJ"), hash_recovery_code("ABCDE-FGHIK"));
+    }
+
+    #[test]
+    fn recovery_codes_are_single_use() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&conn).unwrap();
+        let codes = generate_recovery_codes().unwrap();
+        let hashes: Vec<String> = codes.iter().map(|code| hash_recovery_code(code)).collect();
+        db::save_mfa_enrollment(&conn, "alice@example.com", "secret", &hashes).unwrap();
+
+        let code = codes[0].to_lowercase();
+        assert!(db::use_recovery_code(&conn, "alice@example.com", &hash_recovery_code(&code), 100).unwrap());
+        assert!(!db::use_recovery_code(&conn, "alice@example.com", &hash_recovery_code(&code), 200).unwrap());
+        assert!(!db::use_recovery_code(&conn, "bob@example.com", &hash_recovery_code(&codes[1]), 200).unwrap());
+        assert!(db::use_recovery_code(&conn, "alice@example.com", &hash_recovery_code(&codes[1]), 200).unwrap());
+    }
+}
@@ -0,0 +1,48 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+// Overwrite-then-unlink deletion for files that held sensitive data. On
+// copy-on-write filesystems and SSDs the old blocks may survive an overwrite,
+// so this reduces rather than eliminates recovery; the real protection is
+// keeping plaintext off disk in the first place.
+
+const WIPE_BUFFER_LEN: usize = 64 * 1024;
+
+fn overwrite(path: &Path) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let mut remaining = file.metadata()?.len();
+    let zeros = [0u8; WIPE_BUFFER_LEN];
+    while remaining > 0 {
+        let n = remaining.min(WIPE_BUFFER_LEN as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        remaining -= n as u64;
+    }
+    file.sync_all()?;
+    file.set_len(0)?;
+    file.sync_all()
+}
+
+// Deletes a file, overwriting its contents first when `secure` is set
+pub fn remove_file(path: &Path, secure: bool) -> io::Result<()> {
+    if secure {
+        overwrite(path)?;
+    }
+    fs::remove_file(path)
+}
+
+// Deletes a directory tree, overwriting every file in it first when `secure` is set
+pub fn remove_dir_all(dir: &Path, secure: bool) -> io::Result<()> {
+    if secure {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                remove_dir_all(&entry.path(), true)?;
+            } else if file_type.is_file() {
+                overwrite(&entry.path())?;
+            }
+        }
+    }
+    fs::remove_dir_all(dir)
+}
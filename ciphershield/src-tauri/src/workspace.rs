@@ -0,0 +1,166 @@
+use std::fs::{self, create_dir_all, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use uuid::Uuid;
+use crate::wipe;
+
+// Every processing job gets its own temp directory under
+// `<app data>/temp/<instance id>/<job id>`, readable only by the current user, so
+// concurrent jobs never share intermediate files. The directory is removed when the
+// job's `Workspace` is dropped, whether the job succeeded or failed. With secure
+// deletion enabled, files are overwritten before they are unlinked.
+//
+// Several app instances can run at once, so each holds a lock on
+// `<instance id>.lock` beside its directory while it runs. At startup an instance
+// removes only what instances that are no longer running left behind, and at exit
+// only its own directory.
+
+const TEMP_DIR: &str = "temp";
+const LOCK_EXT: &str = "lock";
+
+// This instance's workspace directory and the lock that marks it in use, kept in managed state
+pub struct Workspaces {
+    dir: PathBuf,
+    lock_path: PathBuf,
+    lock: Mutex<Option<File>>,
+}
+
+impl Workspaces {
+    // Claims a workspace directory for this instance after clearing those left by crashes
+    pub fn init(app: &AppHandle) -> Result<Self, String> {
+        let root = temp_root(app)?;
+        create_private_dir(&root).map_err(|e| e.to_string())?;
+        remove_abandoned(&root);
+        let id = Uuid::new_v4().to_string();
+        let lock_path = root.join(format!("{}.{}", id, LOCK_EXT));
+        let mut lock = File::create_new(&lock_path).map_err(|e| format!("Failed to create workspace lock: {}", e))?;
+        lock.try_lock().map_err(|e| format!("Failed to lock job workspaces: {}", e))?;
+        // Written once locked, so an empty lock file is one still being claimed
+        lock.write_all(id.as_bytes()).map_err(|e| e.to_string())?;
+        let dir = root.join(&id);
+        create_private_dir(&dir).map_err(|e| format!("Failed to create job workspaces: {}", e))?;
+        Ok(Workspaces { dir, lock_path, lock: Mutex::new(Some(lock)) })
+    }
+
+    // Removes this instance's workspaces and releases its lock; called at exit
+    pub fn remove(&self) {
+        // Always wiped since the setting can't be read once the app is closing
+        remove_workspace_dir(&self.dir);
+        drop(self.lock.lock().unwrap_or_else(|e| e.into_inner()).take());
+        if let Err(e) = fs::remove_file(&self.lock_path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to remove workspace lock {:?}: {}", self.lock_path, e);
+            }
+        }
+    }
+}
+
+pub struct Workspace {
+    dir: PathBuf,
+    secure_delete: bool,
+}
+
+impl Workspace {
+    // Creates a fresh workspace for one job
+    pub fn create(app: &AppHandle, secure_delete: bool) -> Result<Self, String> {
+        let dir = app.state::<Workspaces>().dir.join(Uuid::new_v4().to_string());
+        create_private_dir(&dir).map_err(|e| format!("Failed to create job workspace: {}", e))?;
+        Ok(Workspace { dir, secure_delete })
+    }
+
+    pub fn secure_delete(&self) -> bool {
+        self.secure_delete
+    }
+
+    // Path of a file inside the workspace
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if let Err(e) = wipe::remove_dir_all(&self.dir, self.secure_delete) {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to remove job workspace {:?}: {}", self.dir, e);
+            }
+        }
+    }
+}
+
+fn temp_root(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app.path().app_local_data_dir().map_err(|e| e.to_string())?.join(TEMP_DIR))
+}
+
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    create_dir_all(dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+fn remove_workspace_dir(dir: &Path) {
+    match wipe::remove_dir_all(dir, true) {
+        Ok(()) => info!("Removed job workspaces in {:?}", dir),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to remove job workspaces in {:?}: {}", dir, e),
+    }
+}
+
+// Removes the workspaces of instances that aren't running: those whose lock can be taken,
+// and directories without a lock, which earlier versions left directly under the root.
+// Leftovers are always wiped since the setting can't be read this early.
+fn remove_abandoned(root: &Path) {
+    let Ok(entries) = fs::read_dir(root) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_some_and(|ext| ext == LOCK_EXT) {
+            let abandoned = File::options()
+                .write(true)
+                .open(&path)
+                .is_ok_and(|lock| lock.try_lock().is_ok() && lock.metadata().is_ok_and(|m| m.len() > 0));
+            if abandoned {
+                remove_workspace_dir(&path.with_extension(""));
+                let _ = fs::remove_file(&path);
+            }
+        } else if path.is_dir() && !path.with_extension(LOCK_EXT).exists() {
+            remove_workspace_dir(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_removes_workspaces_of_instances_that_stopped() {
+        let root = std::env::temp_dir().join(format!("cs-workspaces-{}", Uuid::new_v4()));
+        let claim = |id: &str| {
+            create_dir_all(root.join(id)).unwrap();
+            let mut lock = File::create_new(root.join(format!("{}.{}", id, LOCK_EXT))).unwrap();
+            lock.write_all(id.as_bytes()).unwrap();
+            lock
+        };
+        let running = claim("running");
+        running.try_lock().unwrap();
+        drop(claim("crashed"));
+        create_dir_all(root.join("legacy-job")).unwrap();
+
+        remove_abandoned(&root);
+        assert!(root.join("running").is_dir());
+        assert!(root.join("running.lock").exists());
+        assert!(!root.join("crashed").exists());
+        assert!(!root.join("crashed.lock").exists());
+        assert!(!root.join("legacy-job").exists());
+        drop(running);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
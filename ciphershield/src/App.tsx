@@ -8,6 +8,8 @@ import { SettingsScreen } from './components/screens/SettingsScreen';
 import { LogsScreen } from './components/screens/LogsScreen';
 import { Toaster } from './components/ui/sonner';
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { setSessionToken as setCommandToken } from './session';
 type AppView = 'login' | 'dashboard' | 'process' | 'settings' | 'logs' | 'help';
 
 export default function App() {
@@ -20,6 +22,7 @@ export default function App() {
   }
   const [currentView, setCurrentView] = useState<AppView>('login');
   const [isLoggedIn, setIsLoggedIn] = useState(false);
+  const [sessionToken, setSessionToken] = useState<string | null>(null);
   const [darkMode, setDarkMode] = useState(false);
 
   // Initialize dark mode from system preference
@@ -37,12 +40,36 @@ export default function App() {
     }
   }, [darkMode]);
 
-  const handleLogin = () => {
+  // Return to the login screen when the backend expires an idle session
+  useEffect(() => {
+    const unlisten = listen('session-expired', () => {
+      setCommandToken(null);
+      setSessionToken(null);
+      setIsLoggedIn(false);
+      setCurrentView('login');
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  const handleLogin = (token: string) => {
+    setCommandToken(token);
+    setSessionToken(token);
     setIsLoggedIn(true);
     setCurrentView('dashboard');
   };
 
-  const handleLogout = () => {
+  const handleLogout = async () => {
+    if (sessionToken) {
+      try {
+        await invoke('logout', { token: sessionToken });
+      } catch (err) {
+        console.error('Logout error:', err);
+      }
+    }
+    setCommandToken(null);
+    setSessionToken(null);
     setIsLoggedIn(false);
     setCurrentView('login');
   };
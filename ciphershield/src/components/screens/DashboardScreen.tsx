@@ -1,4 +1,4 @@
-import React, { useState } from 'react';
+import React, { useCallback, useEffect, useState } from 'react';
 import { Upload, FileText, Settings, BarChart3, Shield, Plus, Clock, CheckCircle } from 'lucide-react';
 import { Button } from '../ui/button';
 import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '../ui/card';
@@ -12,6 +12,22 @@ import { Switch } from '../ui/switch';
 import { Input } from '../ui/input';
 import { open } from '@tauri-apps/plugin-dialog';
 import { invoke } from '@tauri-apps/api/core';
+import { invokeWithSession } from '../../session';
+import { listen } from '@tauri-apps/api/event';
+
+const RECENT_HISTORY_COUNT = 5;
+
+interface EngineState {
+  status: 'starting' | 'ready' | 'crashed';
+  restarts: number;
+  error: string | null;
+}
+
+const ENGINE_LABELS: Record<EngineState['status'], string> = {
+  starting: 'Starting',
+  ready: 'Ready',
+  crashed: 'Restarting',
+};
 
 interface DashboardScreenProps {
   onNavigate: (screen: string) => void;
@@ -38,14 +54,14 @@ export function DashboardScreen({ onNavigate }: DashboardScreenProps) {
     setDragActive(false);
     const files = Array.from(e.dataTransfer.files).map(file => file.path);
     if (files.length > 0) {
-      const result = await invoke('process_files', {
+      const jobId = await invokeWithSession('process_files', {
         input: {
           files,
           action: 'anonymize',
           template_id: null,
         }
       });
-      console.log(result);
+      console.log('Started job', jobId);
       onNavigate('process');
     }
   };
@@ -56,7 +72,7 @@ export function DashboardScreen({ onNavigate }: DashboardScreenProps) {
       filters: [{ name: 'Data Files', extensions: ['pdf', 'csv', 'json', 'xml', 'txt'] }],
     });
     if (Array.isArray(selected) && selected.length > 0) {
-      const result = await invoke('process_files', {
+      const jobId = await invokeWithSession('process_files', {
         input: {
           files: selected,
           action: 'anonymize',
@@ -64,14 +80,14 @@ export function DashboardScreen({ onNavigate }: DashboardScreenProps) {
           save_template: false,
         }
       });
-      console.log(result);
+      console.log('Started job', jobId);
       onNavigate('process');
     }
   };
 
   const handleTextProcess = async () => {
     if (textInput.trim()) {
-      const result = await invoke('process_text', {
+      const result = await invokeWithSession('process_text', {
         input: {
           text: textInput,
           action: 'anonymize',
@@ -86,11 +102,47 @@ export function DashboardScreen({ onNavigate }: DashboardScreenProps) {
     }
   };
 
-  const recentProcesses = [
-    { id: 1, name: 'Customer_Database.csv', status: 'completed', piiFound: 156, time: '2 min ago' },
-    { id: 2, name: 'Employee_Records.xlsx', status: 'processing', piiFound: 89, time: '5 min ago' },
-    { id: 3, name: 'Marketing_Leads.json', status: 'completed', piiFound: 203, time: '1 hour ago' },
-  ];
+  const [recentProcesses, setRecentProcesses] = useState<any[]>([]);
+
+  const loadHistory = useCallback(async () => {
+    try {
+      const history: any[] = await invokeWithSession('list_history');
+      setRecentProcesses(history.slice(0, RECENT_HISTORY_COUNT));
+    } catch (err) {
+      console.error('Failed to load processing history:', err);
+    }
+  }, []);
+
+  useEffect(() => {
+    loadHistory();
+  }, [loadHistory]);
+
+  const [engine, setEngine] = useState<EngineState | null>(null);
+
+  useEffect(() => {
+    invoke<EngineState>('engine_status').then(setEngine).catch((err) => console.error('Failed to load engine status:', err));
+    const unlisten = listen<EngineState>('engine-status', (event) => setEngine(event.payload));
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  const reopenOutput = async (id: number) => {
+    try {
+      await invokeWithSession('reopen_output', { id });
+    } catch (err) {
+      console.error('Failed to open output:', err);
+    }
+  };
+
+  const removeHistoryEntry = async (id: number) => {
+    try {
+      await invokeWithSession('delete_history_entry', { input: { id, delete_output: false } });
+      loadHistory();
+    } catch (err) {
+      console.error('Failed to remove history entry:', err);
+    }
+  };
 
   return (
     <div className="p-6 space-y-6">
@@ -216,12 +268,13 @@ export function DashboardScreen({ onNavigate }: DashboardScreenProps) {
                       <FileText className="h-5 w-5 text-muted-foreground" />
                       <div>
                         <p className="font-medium">{process.name}</p>
-                        <p className="text-sm text-muted-foreground">{process.piiFound} PII elements found • {process.time}</p>
+                        <p className="text-sm text-muted-foreground">{process.pii_count} PII elements found • {new Date(process.timestamp).toLocaleString()}</p>
                       </div>
                     </div>
                     <div className="flex items-center gap-2">
-                      <Badge variant={process.status === 'completed' ? 'default' : 'secondary'}>{process.status}</Badge>
-                      <Button variant="ghost" size="sm">View</Button>
+                      <Badge variant="default">{process.action}</Badge>
+                      <Button variant="ghost" size="sm" onClick={() => reopenOutput(process.id)}>View</Button>
+                      <Button variant="ghost" size="sm" onClick={() => removeHistoryEntry(process.id)}>Remove</Button>
                     </div>
                   </div>
                 ))}
@@ -301,6 +354,15 @@ export function DashboardScreen({ onNavigate }: DashboardScreenProps) {
               <CardTitle>System Status</CardTitle>
             </CardHeader>
             <CardContent className="space-y-4">
+              <div className="flex justify-between items-center text-sm">
+                <span>PII Engine</span>
+                <Badge
+                  variant={engine?.status === 'ready' ? 'default' : engine?.status === 'crashed' ? 'destructive' : 'secondary'}
+                  title={engine?.error ?? undefined}
+                >
+                  {engine ? ENGINE_LABELS[engine.status] : 'Unknown'}
+                </Badge>
+              </div>
               <div className="space-y-2">
                 <div className="flex justify-between text-sm">
                   <span>Processing Queue</span>
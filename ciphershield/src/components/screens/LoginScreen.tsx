@@ -8,7 +8,7 @@ import { Checkbox } from '../ui/checkbox';
 import { invoke } from '@tauri-apps/api/core';
 
 interface LoginScreenProps {
-  onLogin: () => void;
+  onLogin: (token: string) => void;
 }
 
 export function LoginScreen({ onLogin }: LoginScreenProps) {
@@ -17,19 +17,55 @@ export function LoginScreen({ onLogin }: LoginScreenProps) {
   const [password, setPassword] = useState('');
   const [rememberMe, setRememberMe] = useState(false);
   const [error, setError] = useState('');
+  const [mfaChallenge, setMfaChallenge] = useState<string | null>(null);
+  const [mfaEnrollment, setMfaEnrollment] = useState<any>(null);
+  const [mfaCode, setMfaCode] = useState('');
+
+  const resetMfa = () => {
+    setMfaChallenge(null);
+    setMfaEnrollment(null);
+    setMfaCode('');
+  };
 
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
+    setError('');
     try {
       const result = await invoke('login', { input: { email, password } });
       if (result.success) {
-        onLogin();
+        onLogin(result.token);
+      } else if (result.mfa_challenge) {
+        setMfaChallenge(result.mfa_challenge);
+        if (result.mfa_enrollment_required) {
+          setMfaEnrollment(await invoke('start_mfa_enrollment', { challenge: result.mfa_challenge }));
+        }
       } else {
         setError(result.error || 'Login failed');
       }
     } catch (err) {
       console.error('Login error:', err);
-      setError(err.message || 'An error occurred during login');
+      setError(err.message || err || 'An error occurred during login');
+    }
+  };
+
+  const handleMfaSubmit = async (e: React.FormEvent) => {
+    e.preventDefault();
+    setError('');
+    try {
+      const input = { challenge: mfaChallenge, code: mfaCode };
+      const result = mfaEnrollment
+        ? await invoke('confirm_mfa_enrollment', { input })
+        : await invoke('verify_mfa', { input });
+      if (result.success) {
+        resetMfa();
+        onLogin(result.token);
+      } else {
+        setError(result.error || 'Verification failed');
+      }
+    } catch (err) {
+      console.error('MFA error:', err);
+      resetMfa();
+      setError(err.message || err || 'An error occurred during verification');
     }
   };
 
@@ -50,6 +86,42 @@ export function LoginScreen({ onLogin }: LoginScreenProps) {
             {error && <p className="text-destructive text-sm">{error}</p>}
           </CardHeader>
           <CardContent>
+            {mfaChallenge ? (
+            <form onSubmit={handleMfaSubmit} className="space-y-6">
+              {mfaEnrollment && (
+                <div className="space-y-2 text-sm">
+                  <p>
+                    Your administrator requires multi-factor authentication. Add this account to your
+                    authenticator app, then enter the code it shows.
+                  </p>
+                  <p className="font-mono break-all">{mfaEnrollment.secret}</p>
+                  <p className="text-xs text-muted-foreground break-all">{mfaEnrollment.otpauth_uri}</p>
+                  <p>Store these one-time recovery codes somewhere safe:</p>
+                  <p className="font-mono text-xs">{mfaEnrollment.recovery_codes.join('  ')}</p>
+                </div>
+              )}
+              <div className="space-y-2">
+                <Label htmlFor="mfa-code">
+                  {mfaEnrollment ? 'Authenticator Code' : 'Authenticator or Recovery Code'}
+                </Label>
+                <Input
+                  id="mfa-code"
+                  autoComplete="one-time-code"
+                  placeholder="123456"
+                  value={mfaCode}
+                  onChange={(e) => setMfaCode(e.target.value)}
+                  required
+                  className="h-11"
+                />
+              </div>
+              <Button type="submit" className="w-full h-11 bg-primary hover:bg-primary/90" size="lg">
+                Verify
+              </Button>
+              <Button type="button" variant="link" className="w-full text-sm text-muted-foreground" onClick={resetMfa}>
+                Back to sign in
+              </Button>
+            </form>
+            ) : (
             <form onSubmit={handleSubmit} className="space-y-6">
               <div className="space-y-2">
                 <Label htmlFor="email">Email Address</Label>
@@ -116,6 +188,7 @@ export function LoginScreen({ onLogin }: LoginScreenProps) {
                 </div>
               </div>
             </form>
+            )}
           </CardContent>
         </Card>
         <div className="mt-6 text-center text-xs text-muted-foreground">
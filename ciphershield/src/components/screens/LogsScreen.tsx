@@ -1,5 +1,5 @@
-import React, { useState } from 'react';
-import { Search, Filter, Download, RefreshCw, Clock, CheckCircle, XCircle, AlertTriangle, Info } from 'lucide-react';
+import React, { useCallback, useEffect, useState } from 'react';
+import { Search, Filter, Download, RefreshCw, Clock, CheckCircle, XCircle, AlertTriangle, Info, ShieldCheck } from 'lucide-react';
 import { Button } from '../ui/button';
 import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '../ui/card';
 import { Input } from '../ui/input';
@@ -9,105 +9,26 @@ import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from '.
 import { Calendar } from '../ui/calendar';
 import { Popover, PopoverContent, PopoverTrigger } from '../ui/popover';
 import { Checkbox } from '../ui/checkbox';
+import { invokeWithSession } from '../../session';
+import { save } from '@tauri-apps/plugin-dialog';
 
-const logEntries = [
-  {
-    id: 1,
-    timestamp: '2024-01-15 14:32:18',
-    level: 'INFO',
-    operation: 'FILE_PROCESSED',
-    user: 'admin@company.com',
-    filename: 'customer_database.csv',
-    piiFound: 156,
-    status: 'SUCCESS',
-    duration: '2.3s',
-    details: 'Successfully processed file with 156 PII elements detected and anonymized'
-  },
-  {
-    id: 2,
-    timestamp: '2024-01-15 14:28:45',
-    level: 'WARNING',
-    operation: 'HIGH_PII_VOLUME',
-    user: 'admin@company.com',
-    filename: 'employee_records.xlsx',
-    piiFound: 1247,
-    status: 'SUCCESS',
-    duration: '8.7s',
-    details: 'High volume of PII detected (1247 elements) - manual review recommended'
-  },
-  {
-    id: 3,
-    timestamp: '2024-01-15 14:15:22',
-    level: 'ERROR',
-    operation: 'PROCESSING_FAILED',
-    user: 'admin@company.com',
-    filename: 'corrupted_file.pdf',
-    piiFound: 0,
-    status: 'FAILED',
-    duration: '0.5s',
-    details: 'File processing failed: Unsupported file format or corrupted data'
-  },
-  {
-    id: 4,
-    timestamp: '2024-01-15 13:45:12',
-    level: 'INFO',
-    operation: 'LOGIN',
-    user: 'admin@company.com',
-    filename: '-',
-    piiFound: 0,
-    status: 'SUCCESS',
-    duration: '-',
-    details: 'User logged in successfully from IP 192.168.1.100'
-  },
-  {
-    id: 5,
-    timestamp: '2024-01-15 13:22:08',
-    level: 'INFO',
-    operation: 'SETTINGS_UPDATED',
-    user: 'admin@company.com',
-    filename: '-',
-    piiFound: 0,
-    status: 'SUCCESS',
-    duration: '-',
-    details: 'PII detection rules updated - Email anonymization rule modified'
-  },
-  {
-    id: 6,
-    timestamp: '2024-01-15 12:58:33',
-    level: 'INFO',
-    operation: 'EXPORT_GENERATED',
-    user: 'admin@company.com',
-    filename: 'processed_data.csv',
-    piiFound: 89,
-    status: 'SUCCESS',
-    duration: '1.2s',
-    details: 'Anonymized data exported successfully'
-  },
-  {
-    id: 7,
-    timestamp: '2024-01-15 12:35:17',
-    level: 'WARNING',
-    operation: 'FAILED_LOGIN',
-    user: 'unknown@domain.com',
-    filename: '-',
-    piiFound: 0,
-    status: 'FAILED',
-    duration: '-',
-    details: 'Failed login attempt from IP 203.0.113.45 - invalid credentials'
-  },
-  {
-    id: 8,
-    timestamp: '2024-01-15 11:42:55',
-    level: 'INFO',
-    operation: 'TEXT_PROCESSED',
-    user: 'admin@company.com',
-    filename: 'direct_input',
-    piiFound: 12,
-    status: 'SUCCESS',
-    duration: '0.8s',
-    details: 'Direct text input processed - 12 PII elements anonymized'
-  }
-];
+// Loads the most recent entries; filtering below runs on the loaded page
+const AUDIT_PAGE_SIZE = 500;
+
+const formatDuration = (ms: number | null) => (ms == null ? '-' : `${(ms / 1000).toFixed(1)}s`);
+
+const toLogEntry = (entry: any) => ({
+  id: entry.id,
+  timestamp: new Date(entry.timestamp).toLocaleString(),
+  level: entry.level,
+  operation: entry.operation,
+  user: entry.user,
+  filename: entry.source,
+  piiFound: entry.pii_count,
+  status: entry.status,
+  duration: formatDuration(entry.duration_ms),
+  details: entry.details
+});
 
 const getLevelIcon = (level: string) => {
   switch (level) {
@@ -144,6 +65,63 @@ export function LogsScreen({ onBack }: LogsScreenProps) {
   const [selectedStatus, setSelectedStatus] = useState('all');
   const [showFilters, setShowFilters] = useState(false);
   const [selectedLogs, setSelectedLogs] = useState<number[]>([]);
+  const [logEntries, setLogEntries] = useState<any[]>([]);
+  const [chainReport, setChainReport] = useState<any | null>(null);
+  const [chainError, setChainError] = useState<string | null>(null);
+  const [exportFormat, setExportFormat] = useState('csv');
+
+  const loadLogs = useCallback(async () => {
+    try {
+      const page: any = await invokeWithSession('query_audit_log', { query: { page: 0, page_size: AUDIT_PAGE_SIZE } });
+      setLogEntries(page.entries.map(toLogEntry));
+    } catch (err) {
+      console.error('Failed to load audit log:', err);
+    }
+  }, []);
+
+  useEffect(() => {
+    loadLogs();
+  }, [loadLogs]);
+
+  // Exports every stored entry matching the current filters, not just the loaded page
+  const exportLogs = async () => {
+    const extension = exportFormat === 'jsonl' ? 'jsonl' : exportFormat === 'csv' ? 'csv' : 'log';
+    const path = await save({ defaultPath: `ciphershield-audit.${extension}`, filters: [{ name: exportFormat.toUpperCase(), extensions: [extension] }] });
+    if (!path) return;
+    const filter = (value: string) => (value === 'all' ? null : value);
+    try {
+      await invokeWithSession('export_audit_log', {
+        input: {
+          query: {
+            level: filter(selectedLevel),
+            operation: filter(selectedOperation),
+            status: filter(selectedStatus),
+            search: searchTerm || null
+          },
+          format: exportFormat,
+          path
+        }
+      });
+      loadLogs();
+    } catch (err) {
+      console.error('Failed to export audit log:', err);
+    }
+  };
+
+  // Checks the audit hash chain, optionally saving the report for a compliance review
+  const verifyChain = async (exportReport: boolean) => {
+    setChainError(null);
+    try {
+      let exportPath: string | null = null;
+      if (exportReport) {
+        exportPath = await save({ defaultPath: 'audit-chain-report.json', filters: [{ name: 'JSON', extensions: ['json'] }] });
+        if (!exportPath) return;
+      }
+      setChainReport(await invokeWithSession('verify_audit_chain', { exportPath }));
+    } catch (err) {
+      setChainError(`Audit chain verification failed: ${err}`);
+    }
+  };
 
   const filteredLogs = logEntries.filter(log => {
     const matchesSearch = searchTerm === '' || 
@@ -213,7 +191,7 @@ export function LogsScreen({ onBack }: LogsScreenProps) {
               {logEntries.filter(log => log.status === 'SUCCESS').length}
             </div>
             <p className="text-xs text-muted-foreground">
-              {Math.round((logEntries.filter(log => log.status === 'SUCCESS').length / logEntries.length) * 100)}% success rate
+              {logEntries.length ? Math.round((logEntries.filter(log => log.status === 'SUCCESS').length / logEntries.length) * 100) : 0}% success rate
             </p>
           </CardContent>
         </Card>
@@ -262,12 +240,31 @@ export function LogsScreen({ onBack }: LogsScreenProps) {
                 <Filter className="mr-2 h-4 w-4" />
                 {showFilters ? 'Hide' : 'Show'} Filters
               </Button>
-              <Button variant="outline" size="sm">
+              <Button variant="outline" size="sm" onClick={loadLogs}>
                 <RefreshCw className="mr-2 h-4 w-4" />
                 Refresh
               </Button>
+              <Button variant="outline" size="sm" onClick={() => verifyChain(false)}>
+                <ShieldCheck className="mr-2 h-4 w-4" />
+                Verify Integrity
+              </Button>
             </div>
           </div>
+          {chainReport && (
+            <div className={`mt-2 text-sm ${chainReport.valid ? 'text-green-600' : 'text-red-500'}`}>
+              {chainReport.valid
+                ? `Audit trail intact: ${chainReport.verified_entries} entries verified${chainReport.mac_verified ? ' with device key' : ''}.`
+                : `Audit trail broken at entry ${chainReport.first_broken_id}: ${chainReport.reason}`}
+              <Button variant="link" size="sm" onClick={() => verifyChain(true)}>
+                Export report
+              </Button>
+            </div>
+          )}
+          {chainError && (
+            <div className="mt-2 text-red-500 text-sm">
+              {chainError}
+            </div>
+          )}
         </CardHeader>
         <CardContent>
           <div className="space-y-4">
@@ -281,7 +278,18 @@ export function LogsScreen({ onBack }: LogsScreenProps) {
                   className="pl-10"
                 />
               </div>
-              <Button variant="outline">
+              <Select value={exportFormat} onValueChange={setExportFormat}>
+                <SelectTrigger className="w-32">
+                  <SelectValue />
+                </SelectTrigger>
+                <SelectContent>
+                  <SelectItem value="csv">CSV</SelectItem>
+                  <SelectItem value="jsonl">JSON Lines</SelectItem>
+                  <SelectItem value="cef">CEF</SelectItem>
+                  <SelectItem value="syslog">Syslog</SelectItem>
+                </SelectContent>
+              </Select>
+              <Button variant="outline" onClick={exportLogs}>
                 <Download className="mr-2 h-4 w-4" />
                 Export
               </Button>
@@ -315,6 +323,9 @@ export function LogsScreen({ onBack }: LogsScreenProps) {
                       <SelectItem value="FILE_PROCESSED">File Processed</SelectItem>
                       <SelectItem value="TEXT_PROCESSED">Text Processed</SelectItem>
                       <SelectItem value="LOGIN">Login</SelectItem>
+                      <SelectItem value="FAILED_LOGIN">Failed Login</SelectItem>
+                      <SelectItem value="PROCESSING_FAILED">Processing Failed</SelectItem>
+                      <SelectItem value="PROCESSING_CANCELLED">Processing Cancelled</SelectItem>
                       <SelectItem value="EXPORT_GENERATED">Export Generated</SelectItem>
                       <SelectItem value="SETTINGS_UPDATED">Settings Updated</SelectItem>
                     </SelectContent>
@@ -1,5 +1,5 @@
-import React, { useState } from 'react';
-import { Check, ChevronRight, Download, Play, Pause, RotateCcw, Eye, EyeOff, Plus, Trash2 } from 'lucide-react';
+import React, { useEffect, useState } from 'react';
+import { Check, ChevronRight, Download, Play, Pause, RotateCcw, X, Eye, EyeOff, Plus, Trash2 } from 'lucide-react';
 import { Button } from '../ui/button';
 import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '../ui/card';
 import { Badge } from '../ui/badge';
@@ -8,7 +8,8 @@ import { Table, TableBody, TableCell, TableHead, TableHeader, TableRow } from '.
 import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from '../ui/select';
 import { Textarea } from '../ui/textarea';
 import { Input } from '../ui/input';
-import { invoke } from '@tauri-apps/api/core';
+import { invokeWithSession } from '../../session';
+import { listen } from '@tauri-apps/api/event';
 
 const steps = [
   { id: 1, name: 'Input', completed: true },
@@ -32,6 +33,21 @@ interface CustomRecognizer {
   confidence: number;
 }
 
+interface JobProgress {
+  job_id: string;
+  input_path: string;
+  stage: 'encrypting' | 'sending' | 'analyzing' | 'decrypting' | 'receiving' | 'done';
+  percent: number;
+  status: 'success' | 'skipped' | 'failed' | 'cancelled' | null;
+}
+
+interface JobFinished {
+  job_id: string;
+  cancelled: boolean;
+  output: { error: string | null } | null;
+  error: string | null;
+}
+
 interface ProcessingScreenProps {
   onBack: () => void;
 }
@@ -50,6 +66,55 @@ export function ProcessingScreen({ onBack }: ProcessingScreenProps) {
   const [newPattern, setNewPattern] = useState('');
   const [newConfidence, setNewConfidence] = useState(0.8);
   const [error, setError] = useState<string | null>(null);
+  const [jobId, setJobId] = useState<string | null>(null);
+  const [stageLabel, setStageLabel] = useState('Processing...');
+  const [fileProgress, setFileProgress] = useState<Record<string, number>>({});
+
+  // Follow file jobs started from the dashboard
+  useEffect(() => {
+    const unlistenProgress = listen<JobProgress>('job-progress', (event) => {
+      const { job_id, input_path, stage, percent } = event.payload;
+      const name = input_path.split(/[\\/]/).pop();
+      setJobId(job_id);
+      setProcessing(true);
+      setStageLabel(stage === 'done' ? `Finished ${name}` : `${stage.charAt(0).toUpperCase() + stage.slice(1)} ${name}...`);
+      setFileProgress((prev) => ({ ...prev, [input_path]: percent }));
+    });
+    const unlistenFinished = listen<JobFinished>('job-finished', (event) => {
+      const { cancelled, output, error } = event.payload;
+      setJobId(null);
+      setProcessing(false);
+      setFileProgress({});
+      setStageLabel('Processing...');
+      if (cancelled) {
+        setError('Processing cancelled');
+      } else if (error || output?.error) {
+        setError(`Processing failed: ${error ?? output?.error}`);
+      } else {
+        setProgress(100);
+      }
+    });
+    return () => {
+      unlistenProgress.then((fn) => fn());
+      unlistenFinished.then((fn) => fn());
+    };
+  }, []);
+
+  useEffect(() => {
+    const values = Object.values(fileProgress);
+    if (values.length > 0) {
+      setProgress(Math.round(values.reduce((sum, p) => sum + p, 0) / values.length));
+    }
+  }, [fileProgress]);
+
+  const handleCancel = async () => {
+    if (!jobId) return;
+    try {
+      await invokeWithSession('cancel_job', { jobId });
+    } catch (e) {
+      setError(`Failed to cancel: ${e}`);
+    }
+  };
 
   const addCustomRecognizer = () => {
     if (newEntityType && newPattern) {
@@ -69,7 +134,7 @@ export function ProcessingScreen({ onBack }: ProcessingScreenProps) {
     setProgress(20);
     setError(null);
     try {
-      const result = await invoke('process_text', {
+      const result = await invokeWithSession('process_text', {
         input: {
           text: textInput,
           action: 'anonymize',
@@ -101,7 +166,7 @@ export function ProcessingScreen({ onBack }: ProcessingScreenProps) {
     if (templateName) {
       setError(null);
       try {
-        await invoke('process_text', {
+        await invokeWithSession('process_text', {
           input: {
             text: textInput,
             action: 'anonymize',
@@ -163,7 +228,7 @@ export function ProcessingScreen({ onBack }: ProcessingScreenProps) {
           {processing && (
             <div className="mt-4">
               <div className="flex justify-between text-sm mb-2">
-                <span>Processing...</span>
+                <span>{stageLabel}</span>
                 <span>{progress}%</span>
               </div>
               <Progress value={progress} className="h-2" />
@@ -425,7 +490,12 @@ export function ProcessingScreen({ onBack }: ProcessingScreenProps) {
               <Button variant="outline" onClick={handleSaveTemplate} disabled={!templateName}>
                 Save as Template
               </Button>
-              {processing ? (
+              {jobId ? (
+                <Button variant="outline" onClick={handleCancel}>
+                  <X className="mr-2 h-4 w-4" />
+                  Cancel
+                </Button>
+              ) : processing ? (
                 <Button variant="outline" disabled>
                   <Pause className="mr-2 h-4 w-4" />
                   Pause
@@ -1,4 +1,4 @@
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
 import { Shield, User, Lock, Bell, Database, Key, Globe, Trash2, Plus, Edit } from 'lucide-react';
 import { Button } from '../ui/button';
 import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '../ui/card';
@@ -11,6 +11,8 @@ import { Textarea } from '../ui/textarea';
 import { Badge } from '../ui/badge';
 import { Table, TableBody, TableCell, TableHead, TableHeader, TableRow } from '../ui/table';
 import { Separator } from '../ui/separator';
+import { invoke } from '@tauri-apps/api/core';
+import { invokeWithSession } from '../../session';
 
 const piiRules = [
   { id: 1, name: 'Email Addresses', pattern: '[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\\.[a-zA-Z]{2,}', enabled: true, action: 'Anonymize' },
@@ -26,6 +28,25 @@ interface SettingsScreenProps {
 
 export function SettingsScreen({ onBack }: SettingsScreenProps) {
   const [activeTab, setActiveTab] = useState('general');
+  const [securitySettings, setSecuritySettings] = useState<any>(null);
+
+  useEffect(() => {
+    invoke('get_security_settings')
+      .then(setSecuritySettings)
+      .catch((err) => console.error('Failed to load security settings:', err));
+  }, []);
+
+  const updateSecuritySettings = async (changes: Record<string, unknown>) => {
+    try {
+      const saved = await invokeWithSession('update_security_settings', { settings: { ...securitySettings, ...changes } });
+      setSecuritySettings(saved);
+    } catch (err) {
+      console.error('Failed to save security settings:', err);
+    }
+  };
+
+  const updateAuditForwarding = (changes: Record<string, unknown>) =>
+    updateSecuritySettings({ audit_forwarding: { ...securitySettings.audit_forwarding, ...changes } });
 
   return (
     <div className="p-6 space-y-6">
@@ -417,7 +438,12 @@ export function SettingsScreen({ onBack }: SettingsScreenProps) {
                 <div className="space-y-3">
                   <div className="flex items-center justify-between">
                     <Label htmlFor="require-mfa">Require multi-factor authentication</Label>
-                    <Switch id="require-mfa" defaultChecked />
+                    <Switch
+                      id="require-mfa"
+                      checked={securitySettings?.require_mfa ?? false}
+                      disabled={!securitySettings}
+                      onCheckedChange={(checked) => updateSecuritySettings({ require_mfa: checked })}
+                    />
                   </div>
                   <div className="flex items-center justify-between">
                     <Label htmlFor="remember-device">Allow device remembering</Label>
@@ -487,8 +513,30 @@ export function SettingsScreen({ onBack }: SettingsScreenProps) {
                   </div>
                   <div className="flex items-center justify-between">
                     <Label htmlFor="secure-delete">Secure file deletion</Label>
-                    <Switch id="secure-delete" defaultChecked />
+                    <Switch
+                      id="secure-delete"
+                      checked={securitySettings?.secure_delete ?? true}
+                      disabled={!securitySettings}
+                      onCheckedChange={(checked) => updateSecuritySettings({ secure_delete: checked })}
+                    />
                   </div>
+                  {securitySettings && (
+                    <div className="flex items-center justify-between">
+                      <Label>Engine transport</Label>
+                      <Select
+                        value={securitySettings.engine_transport}
+                        onValueChange={(engine_transport) => updateSecuritySettings({ engine_transport })}
+                      >
+                        <SelectTrigger className="w-36">
+                          <SelectValue />
+                        </SelectTrigger>
+                        <SelectContent>
+                          <SelectItem value="socket">Unix socket</SelectItem>
+                          <SelectItem value="https">HTTPS</SelectItem>
+                        </SelectContent>
+                      </Select>
+                    </div>
+                  )}
                 </div>
               </CardContent>
             </Card>
@@ -544,6 +592,37 @@ export function SettingsScreen({ onBack }: SettingsScreenProps) {
                       <Label htmlFor="log-exports">Log data exports</Label>
                       <Switch id="log-exports" defaultChecked />
                     </div>
+                    <div className="flex items-center justify-between">
+                      <Label htmlFor="syslog-forwarding">Forward to syslog</Label>
+                      <Switch
+                        id="syslog-forwarding"
+                        checked={securitySettings?.audit_forwarding?.enabled ?? false}
+                        disabled={!securitySettings}
+                        onCheckedChange={(checked) => updateAuditForwarding({ enabled: checked })}
+                      />
+                    </div>
+                    {securitySettings?.audit_forwarding?.enabled && (
+                      <div className="flex gap-2">
+                        <Input
+                          key={securitySettings.audit_forwarding.target}
+                          defaultValue={securitySettings.audit_forwarding.target}
+                          placeholder="/dev/log or udp://127.0.0.1:514"
+                          onBlur={(e) => updateAuditForwarding({ target: e.target.value })}
+                        />
+                        <Select
+                          value={securitySettings.audit_forwarding.format}
+                          onValueChange={(format) => updateAuditForwarding({ format })}
+                        >
+                          <SelectTrigger className="w-28">
+                            <SelectValue />
+                          </SelectTrigger>
+                          <SelectContent>
+                            <SelectItem value="syslog">Syslog</SelectItem>
+                            <SelectItem value="cef">CEF</SelectItem>
+                          </SelectContent>
+                        </Select>
+                      </div>
+                    )}
                   </div>
                 </div>
 
@@ -0,0 +1,13 @@
+import { invoke } from '@tauri-apps/api/core';
+
+// Token of the signed-in session; commands that need a session must present it
+let sessionToken: string | null = null;
+
+export function setSessionToken(token: string | null) {
+  sessionToken = token;
+}
+
+// Invokes a backend command on behalf of the signed-in session
+export function invokeWithSession<T = unknown>(cmd: string, args: Record<string, unknown> = {}): Promise<T> {
+  return invoke<T>(cmd, { ...args, token: sessionToken });
+}